@@ -6,11 +6,7 @@ use playdate::graphics;
 use anyhow::Result;
 use serde::{Serialize, Deserialize};
 
-const INIT_X: i32 = (400 - TEXT_WIDTH) / 2;
-const INIT_Y: i32 = (240 - TEXT_HEIGHT) / 2;
-
-const TEXT_WIDTH: i32 = 86;
-const TEXT_HEIGHT: i32 = 16;
+const TEXT: &str = "hello rust";
 
 #[derive(Default, Copy, Clone)]
 struct State {
@@ -18,6 +14,8 @@ struct State {
     y: i32,
     dx: i32,
     dy: i32,
+    text_width: i32,
+    text_height: i32,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -32,11 +30,15 @@ impl Game for State {
     fn init(playdate: &mut Playdate) -> Self {
         let font = graphics::Font::load("/System/Fonts/Asheville-Sans-14-Bold.pft").unwrap();
         playdate.graphics().set_font(font);
+        let text_width = font.text_width(TEXT, 0);
+        let text_height = font.height();
         Self {
-            x: INIT_X,
-            y: INIT_Y,
+            x: (graphics::COLUMNS as i32 - text_width) / 2,
+            y: (graphics::ROWS as i32 - text_height) / 2,
             dx: 1,
             dy: 2,
+            text_width,
+            text_height,
         }
     }
     fn update(&mut self, playdate: &mut Playdate) -> Result<()> {
@@ -44,17 +46,17 @@ impl Game for State {
             graphics::SolidColor::kColorWhite));
         playdate.graphics()
             .draw_text(
-                "hello rust",
+                TEXT,
                 graphics::StringEncoding::kASCIIEncoding,
                 self.x,
                 self.y,
             );
         self.x += self.dx;
         self.y += self.dy;
-        if self.x < 0 || self.x > graphics::COLUMNS as i32 - TEXT_WIDTH {
+        if self.x < 0 || self.x > graphics::COLUMNS as i32 - self.text_width {
             self.dx = -self.dx;
         }
-        if self.y < 0 || self.y > graphics::ROWS as i32 - TEXT_HEIGHT {
+        if self.y < 0 || self.y > graphics::ROWS as i32 - self.text_height {
             self.dy = -self.dy;
         }
         playdate.system().draw_fps(0, 0);
@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use anyhow::{Result, Context};
+use playdate_assets::{FontOptions, font};
+
+const USAGE: &str = "usage: playdate-font [-o <out dir>] [--name <name>] [--size <px>] \
+[--tracking <px>] [--chars <a-z,U+00C0-U+00FF,...>] [--chars-from <text file>] <font.ttf|font.bdf>";
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{:#}", err);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let mut out_dir = PathBuf::from(".");
+    let mut name = None;
+    let mut input = None;
+    let mut options = FontOptions::default();
+    let mut charset: Option<Vec<char>> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "-o" => out_dir = PathBuf::from(value()),
+            "--name" => name = Some(value()),
+            "--size" => options.size = value().parse().context("bad --size")?,
+            "--tracking" => options.tracking = value().parse().context("bad --tracking")?,
+            "--chars" => charset.get_or_insert_with(Vec::new)
+                .extend(font::parse_charset(&value())?),
+            "--chars-from" => {
+                let path = value();
+                let text = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path))?;
+                charset.get_or_insert_with(Vec::new)
+                    .extend(text.chars().filter(|c| !c.is_control()));
+            }
+            "-h" | "--help" => usage(),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => usage(),
+        }
+    }
+    let input = input.unwrap_or_else(|| usage());
+    if let Some(mut charset) = charset {
+        charset.sort_unstable();
+        charset.dedup();
+        options.charset = charset;
+    }
+    let name = match name {
+        Some(name) => name,
+        None => input.file_stem().and_then(|s| s.to_str())
+            .context("bad font file name")?.to_string(),
+    };
+    let (fnt, sheet) = playdate_assets::convert_font(&input, &name, &out_dir, &options)?;
+    println!("{} -> {}, {}", input.display(), fnt.display(), sheet.display());
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
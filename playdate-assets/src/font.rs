@@ -0,0 +1,457 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt::Write;
+use anyhow::{Result, Context, bail, ensure};
+use crate::image::Image;
+
+pub const ASCII: std::ops::RangeInclusive<char> = ' '..='~';
+
+// One glyph, drawn with its pen position at the left edge of a
+// `BitmapFont::cell_width` x `cell_height` cell and its baseline
+// `ascent` pixels down from the top. Set pixels are ink.
+pub struct Glyph {
+    pub code: char,
+    pub advance: i32,
+    pub ink: Vec<bool>,
+}
+
+// The data behind a Playdate `.fnt`: a text file of advances and kerning
+// pairs, plus a `name-table-W-H.png` sheet holding one cell per glyph in
+// the same order.
+pub struct BitmapFont {
+    pub cell_width: usize,
+    pub cell_height: usize,
+    pub tracking: i32,
+    pub glyphs: Vec<Glyph>,
+    pub kerning: Vec<(char, char, i32)>,
+}
+
+impl BitmapFont {
+    pub fn to_fnt(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "tracking={}", self.tracking).unwrap();
+        for glyph in &self.glyphs {
+            writeln!(out, "{}\t{}", glyph_name(glyph.code), glyph.advance).unwrap();
+        }
+        // Fields are split on whitespace, so a pair containing a space or a
+        // tab cannot be written.
+        for &(left, right, amount) in &self.kerning {
+            if left.is_whitespace() || right.is_whitespace() {
+                continue;
+            }
+            writeln!(out, "{}{}\t{}", left, right, amount).unwrap();
+        }
+        out
+    }
+
+    // The glyph sheet, `columns` cells wide, ink black on transparent.
+    pub fn sheet(&self, columns: usize) -> Image {
+        let columns = columns.max(1).min(self.glyphs.len().max(1));
+        let rows = self.glyphs.len().div_ceil(columns);
+        let width = columns * self.cell_width;
+        let height = rows.max(1) * self.cell_height;
+        let mut image = Image {
+            width,
+            height,
+            white: vec![false; width * height],
+            opaque: vec![false; width * height],
+        };
+        for (i, glyph) in self.glyphs.iter().enumerate() {
+            let (cx, cy) = (i % columns * self.cell_width, i / columns * self.cell_height);
+            for y in 0..self.cell_height {
+                for x in 0..self.cell_width {
+                    image.opaque[(cy + y) * width + cx + x] = glyph.ink[y * self.cell_width + x];
+                }
+            }
+        }
+        image
+    }
+
+    pub fn sheet_name(&self, name: &str) -> String {
+        format!("{}-table-{}-{}.png", name, self.cell_width, self.cell_height)
+    }
+}
+
+fn glyph_name(code: char) -> String {
+    match code {
+        ' ' => String::from("space"),
+        c => c.to_string(),
+    }
+}
+
+// Parses a charset description: a comma-separated list of single
+// characters, `U+XXXX`/`0xXX` code points or `a-b` ranges of either.
+pub fn parse_charset(spec: &str) -> Result<Vec<char>> {
+    let mut chars = Vec::new();
+    for part in spec.split(',').filter(|part| !part.is_empty()) {
+        let mut ends = part.splitn(2, '-').filter(|end| !end.is_empty());
+        let first = parse_char(ends.next().context("empty charset range")?)?;
+        let last = match ends.next() {
+            Some(end) => parse_char(end)?,
+            None => first,
+        };
+        ensure!(first <= last, "charset range {} is backwards", part);
+        chars.extend(first..=last);
+    }
+    Ok(chars)
+}
+
+fn parse_char(s: &str) -> Result<char> {
+    let hex = s.strip_prefix("U+").or_else(|| s.strip_prefix("0x"));
+    let code = match hex {
+        Some(hex) => u32::from_str_radix(hex, 16).with_context(|| format!("bad code point {}", s))?,
+        None => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c as u32,
+                _ => bail!("bad charset entry {}", s),
+            }
+        }
+    };
+    std::char::from_u32(code).with_context(|| format!("invalid code point {}", s))
+}
+
+// A glyph's ink relative to its pen position and baseline, before it is
+// placed into a cell. `top` is the distance from the baseline up to the
+// first row, so rows grow downwards from there.
+struct Raster {
+    code: char,
+    advance: i32,
+    left: i32,
+    top: i32,
+    width: usize,
+    height: usize,
+    ink: Vec<bool>,
+}
+
+fn build(rasters: Vec<Raster>, ascent: i32, descent: i32, tracking: i32,
+         kerning: Vec<(char, char, i32)>) -> Result<BitmapFont> {
+    ensure!(!rasters.is_empty(), "none of the requested characters are in the font");
+    let cell_height = (ascent + descent).max(1) as usize;
+    let cell_width = rasters.iter()
+        .map(|r| r.advance.max(r.left + r.width as i32))
+        .max()
+        .unwrap_or(1)
+        .max(1) as usize;
+    let glyphs = rasters.into_iter().map(|r| {
+        let mut ink = vec![false; cell_width * cell_height];
+        for y in 0..r.height {
+            let cy = ascent - r.top + y as i32;
+            if cy < 0 || cy >= cell_height as i32 {
+                continue;
+            }
+            for x in 0..r.width {
+                let cx = r.left + x as i32;
+                if cx >= 0 && cx < cell_width as i32 && r.ink[y * r.width + x] {
+                    ink[cy as usize * cell_width + cx as usize] = true;
+                }
+            }
+        }
+        Glyph { code: r.code, advance: r.advance, ink }
+    }).collect();
+    Ok(BitmapFont { cell_width, cell_height, tracking, glyphs, kerning })
+}
+
+// Rasterizes a TrueType or OpenType font at `size` pixels per em. Pixels
+// at least half covered become ink.
+pub fn rasterize_ttf(data: &[u8], size: f32, charset: &[char], tracking: i32)
+                     -> Result<BitmapFont> {
+    let font = fontdue::Font::from_bytes(data, fontdue::FontSettings::default())
+        .map_err(|err| anyhow::anyhow!("failed to parse font: {}", err))?;
+    let line = font.horizontal_line_metrics(size).context("font has no horizontal metrics")?;
+    let ascent = line.ascent.ceil() as i32;
+    let descent = (-line.descent).ceil() as i32;
+    let mut rasters = Vec::new();
+    for &code in charset {
+        if font.lookup_glyph_index(code) == 0 && code != ' ' {
+            continue;
+        }
+        let (metrics, coverage) = font.rasterize(code, size);
+        rasters.push(Raster {
+            code,
+            advance: metrics.advance_width.round() as i32,
+            left: metrics.xmin,
+            top: metrics.ymin + metrics.height as i32,
+            width: metrics.width,
+            height: metrics.height,
+            ink: coverage.iter().map(|&c| c >= 128).collect(),
+        });
+    }
+    // Only pairs listed in the kern table can be non-zero, so look those up
+    // instead of every pair of the charset.
+    let mut chars: HashMap<u16, Vec<char>> = HashMap::new();
+    for raster in &rasters {
+        let index = font.lookup_glyph_index(raster.code);
+        if index != 0 {
+            chars.entry(index).or_default().push(raster.code);
+        }
+    }
+    let mut kerning = Vec::new();
+    for (left, right) in kern_pairs(data).unwrap_or_default() {
+        let (lefts, rights) = match (chars.get(&left), chars.get(&right)) {
+            (Some(lefts), Some(rights)) => (lefts, rights),
+            _ => continue,
+        };
+        let kern = font.horizontal_kern_indexed(left, right, size).unwrap_or(0.0).round() as i32;
+        if kern != 0 {
+            for &l in lefts {
+                kerning.extend(rights.iter().map(|&r| (l, r, kern)));
+            }
+        }
+    }
+    kerning.sort_unstable();
+    build(rasters, ascent, descent, tracking, kerning)
+}
+
+// The glyph pairs of the first horizontal subtable of the font's `kern`
+// table, which is where fontdue reads kerning from, when that subtable is
+// an ordered pair list (format 0). Other formats are not exported.
+fn kern_pairs(data: &[u8]) -> Option<Vec<(u16, u16)>> {
+    let read = |offset: usize, len: usize| data.get(offset..offset.checked_add(len)?);
+    let u16_at = |offset| read(offset, 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let u32_at = |offset| read(offset, 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+    // A collection is read from its first font, like fontdue does.
+    let font = if read(0, 4)? == b"ttcf" { u32_at(12)? as usize } else { 0 };
+    let kern = (0..u16_at(font + 4)? as usize)
+        .map(|i| font + 12 + 16 * i)
+        .find(|&record| read(record, 4) == Some(&b"kern"[..]))?;
+    let start = u32_at(kern + 8)? as usize;
+    // Microsoft's version 0 header and subtables, or Apple's version 1.
+    let apple = u16_at(start)? == 1;
+    let (count, mut offset) = if apple {
+        (u32_at(start + 4)? as usize, start + 8)
+    } else {
+        (u16_at(start + 2)? as usize, start + 4)
+    };
+    for _ in 0..count {
+        let (length, format, horizontal, header) = if apple {
+            let coverage = u16_at(offset + 4)?;
+            (u32_at(offset)? as usize, coverage & 0xFF, coverage & 0x8000 == 0, 8)
+        } else {
+            let coverage = u16_at(offset + 4)?;
+            (u16_at(offset + 2)? as usize, coverage >> 8, coverage & 1 != 0, 6)
+        };
+        if horizontal && format == 0 {
+            let pairs = offset + header + 8;
+            return (0..u16_at(offset + header)? as usize)
+                .map(|i| Some((u16_at(pairs + 6 * i)?, u16_at(pairs + 6 * i + 2)?)))
+                .collect();
+        }
+        if horizontal && format == 3 {
+            return None;
+        }
+        offset += length;
+    }
+    None
+}
+
+// Reads the glyphs of a BDF bitmap font. Its pixels are used as they are,
+// so there is no size to pick.
+pub fn parse_bdf(text: &str, charset: &[char], tracking: i32) -> Result<BitmapFont> {
+    let mut ascent = None;
+    let mut descent = None;
+    let mut bounding_box = None;
+    let mut found = BTreeMap::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("FONTBOUNDINGBOX") => bounding_box = Some(numbers(words)?),
+            Some("FONT_ASCENT") => ascent = Some(numbers(words)?[0]),
+            Some("FONT_DESCENT") => descent = Some(numbers(words)?[0]),
+            Some("STARTCHAR") => {
+                if let Some(raster) = parse_bdf_char(&mut lines)? {
+                    found.insert(raster.code, raster);
+                }
+            }
+            _ => {}
+        }
+    }
+    let bbx = bounding_box.context("BDF font has no FONTBOUNDINGBOX")?;
+    ensure!(bbx.len() == 4, "malformed FONTBOUNDINGBOX");
+    let ascent = ascent.unwrap_or(bbx[1] + bbx[3]);
+    let descent = descent.unwrap_or(-bbx[3]);
+    let rasters = charset.iter().filter_map(|c| found.remove(c)).collect();
+    build(rasters, ascent, descent, tracking, Vec::new())
+}
+
+fn parse_bdf_char<'a, I: Iterator<Item = &'a str>>(lines: &mut I) -> Result<Option<Raster>> {
+    let mut code = None;
+    let mut advance = 0;
+    let mut bbx = vec![0; 4];
+    for line in lines.by_ref() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("ENCODING") => code = Some(numbers(words)?[0]),
+            Some("DWIDTH") => advance = numbers(words)?[0],
+            Some("BBX") => bbx = numbers(words)?,
+            Some("BITMAP") => break,
+            Some("ENDCHAR") => bail!("BDF glyph has no BITMAP"),
+            _ => {}
+        }
+    }
+    ensure!(bbx.len() == 4, "malformed BBX");
+    let (width, height) = (bbx[0].max(0) as usize, bbx[1].max(0) as usize);
+    let mut ink = Vec::with_capacity(width * height);
+    for _ in 0..height {
+        let row = lines.next().context("BDF glyph bitmap is truncated")?.trim();
+        let bytes = (0..row.len() / 2)
+            .map(|i| u8::from_str_radix(&row[2 * i..2 * i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .context("bad BDF bitmap row")?;
+        ensure!(bytes.len() * 8 >= width, "BDF bitmap row too short");
+        ink.extend((0..width).map(|x| bytes[x / 8] & (0x80 >> (x % 8)) != 0));
+    }
+    let code = match code.and_then(|code| u32::try_from(code).ok()).and_then(std::char::from_u32) {
+        Some(code) => code,
+        None => return Ok(None),
+    };
+    Ok(Some(Raster { code, advance, left: bbx[2], top: bbx[3] + bbx[1], width, height, ink }))
+}
+
+fn numbers<'a, I: Iterator<Item = &'a str>>(words: I) -> Result<Vec<i32>> {
+    let numbers = words.map(|word| word.parse::<i32>()).collect::<Result<Vec<_>, _>>()?;
+    ensure!(!numbers.is_empty(), "missing BDF value");
+    Ok(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(font: &BitmapFont, glyph: &Glyph) -> Vec<String> {
+        glyph.ink.chunks(font.cell_width)
+            .map(|row| row.iter().map(|&ink| if ink { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn parses_charsets() {
+        let chars = parse_charset("a-c,U+00E9,0x30-0x31,U+002D,,~").unwrap();
+        assert_eq!(chars, ['a', 'b', 'c', 'é', '0', '1', '-', '~']);
+        assert_eq!(parse_charset("").unwrap(), []);
+        assert_eq!(parse_charset(" -!").unwrap(), [' ', '!']);
+    }
+
+    #[test]
+    fn rejects_bad_charsets() {
+        for spec in ["c-a", "ab", "U+ZZ", "U+D800", "-", "a-bc"].iter() {
+            assert!(parse_charset(spec).is_err(), "{}", spec);
+        }
+    }
+
+    const BDF: &str = "STARTFONT 2.1
+FONT -test-medium-r-normal--6-60-75-75-c-40-iso10646-1
+SIZE 6 75 75
+FONTBOUNDINGBOX 3 6 0 -1
+STARTPROPERTIES 2
+FONT_ASCENT 5
+FONT_DESCENT 1
+ENDPROPERTIES
+CHARS 2
+STARTCHAR A
+ENCODING 65
+SWIDTH 666 0
+DWIDTH 4 0
+BBX 3 5 0 0
+BITMAP
+40
+A0
+E0
+A0
+A0
+ENDCHAR
+STARTCHAR g
+ENCODING 103
+SWIDTH 666 0
+DWIDTH 4 0
+BBX 3 4 0 -1
+BITMAP
+60
+A0
+60
+C0
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn parses_bdf_glyphs_onto_the_baseline() {
+        let font = parse_bdf(BDF, &['g', 'A', 'z'], 1).unwrap();
+        assert_eq!((font.cell_width, font.cell_height, font.tracking), (4, 6, 1));
+        let codes: Vec<char> = font.glyphs.iter().map(|glyph| glyph.code).collect();
+        assert_eq!(codes, ['g', 'A']);
+        assert_eq!(rows(&font, &font.glyphs[0]), ["....", "....", ".##.", "#.#.", ".##.", "##.."]);
+        assert_eq!(rows(&font, &font.glyphs[1]), [".#..", "#.#.", "###.", "#.#.", "#.#.", "...."]);
+        assert_eq!(font.glyphs[1].advance, 4);
+    }
+
+    #[test]
+    fn rejects_broken_bdf() {
+        assert!(parse_bdf("STARTCHAR A\nENCODING 65\nENDCHAR\n", &['A'], 0).is_err());
+        assert!(parse_bdf(&BDF.replace("FONTBOUNDINGBOX 3 6 0 -1\n", ""), &['A'], 0).is_err());
+        assert!(parse_bdf(&BDF.replace("C0\n", ""), &['g'], 0).is_err());
+        assert!(parse_bdf(BDF, &['z'], 0).is_err());
+    }
+
+    #[test]
+    fn fnt_skips_kerning_with_whitespace() {
+        let glyph = |code, advance| Glyph { code, advance, ink: Vec::new() };
+        let font = BitmapFont {
+            cell_width: 1,
+            cell_height: 1,
+            tracking: 1,
+            glyphs: vec![glyph(' ', 3), glyph('A', 5), glyph('V', 5)],
+            kerning: vec![(' ', 'A', -2), ('A', 'V', -1), ('A', '\t', 1)],
+        };
+        assert_eq!(font.to_fnt(), "tracking=1\nspace\t3\nA\t5\nV\t5\nAV\t-1\n");
+    }
+
+    fn be16(out: &mut Vec<u8>, values: &[u16]) {
+        for value in values {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    // An sfnt holding only `kern` after a dummy table record.
+    fn sfnt(kern: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        be16(&mut out, &[1, 0, 2, 0, 0, 0]);
+        out.extend_from_slice(b"cmap");
+        out.extend_from_slice(&[0; 12]);
+        out.extend_from_slice(b"kern");
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&44u32.to_be_bytes());
+        out.extend_from_slice(&(kern.len() as u32).to_be_bytes());
+        out.extend_from_slice(kern);
+        out
+    }
+
+    #[test]
+    fn reads_pairs_from_the_kern_table() {
+        let mut kern = Vec::new();
+        be16(&mut kern, &[0, 2]);
+        // A vertical subtable, skipped.
+        be16(&mut kern, &[0, 20, 0x0000, 1, 0, 0, 0, 1, 2, 10]);
+        be16(&mut kern, &[0, 26, 0x0001, 2, 0, 0, 0, 3, 4, (-50i16) as u16, 5, 6, 20]);
+        assert_eq!(kern_pairs(&sfnt(&kern)), Some(vec![(3, 4), (5, 6)]));
+    }
+
+    #[test]
+    fn reads_apple_kern_tables() {
+        let mut kern = Vec::new();
+        be16(&mut kern, &[1, 0, 0, 1]);
+        be16(&mut kern, &[0, 22, 0x0000, 0, 1, 0, 0, 0, 7, 8, 5]);
+        assert_eq!(kern_pairs(&sfnt(&kern)), Some(vec![(7, 8)]));
+    }
+
+    #[test]
+    fn fonts_without_kern_tables_have_no_pairs() {
+        let mut out = Vec::new();
+        be16(&mut out, &[1, 0, 1, 0, 0, 0]);
+        out.extend_from_slice(b"cmap");
+        out.extend_from_slice(&[0; 12]);
+        assert_eq!(kern_pairs(&out), None);
+        assert_eq!(kern_pairs(&[]), None);
+    }
+}
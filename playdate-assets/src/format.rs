@@ -0,0 +1,164 @@
+use std::convert::TryFrom;
+use anyhow::{Result, Context, ensure};
+use crate::image::Image;
+
+// The compiled image formats read by loadBitmap and loadBitmapTable. Files
+// are written uncompressed (flags 0), which the loader accepts alongside
+// pdc's zlib-compressed output.
+const PDI_MAGIC: &[u8; 12] = b"Playdate IMG";
+const PDT_MAGIC: &[u8; 12] = b"Playdate IMT";
+const CELL_HAS_MASK: u16 = 0x3;
+
+pub fn encode_pdi(image: &Image) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    out.extend_from_slice(PDI_MAGIC);
+    out.extend_from_slice(&0u32.to_le_bytes());
+    encode_cell(image, &mut out)?;
+    Ok(out)
+}
+
+// Cells are stored back to back after a table of each cell's offset,
+// measured from the end of that table.
+pub fn encode_pdt(cells: &[Image], cells_per_row: usize) -> Result<Vec<u8>> {
+    ensure!(!cells.is_empty(), "image table has no cells");
+    let mut data = Vec::new();
+    let mut offsets = Vec::with_capacity(cells.len());
+    for cell in cells {
+        offsets.push(u32::try_from(data.len())?);
+        encode_cell(cell, &mut data)?;
+    }
+    let mut out = Vec::with_capacity(24 + 4 * cells.len() + data.len());
+    out.extend_from_slice(PDT_MAGIC);
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&to_u16(cells.len(), "cell count")?.to_le_bytes());
+    out.extend_from_slice(&to_u16(cells_per_row, "cells per row")?.to_le_bytes());
+    for offset in offsets {
+        out.extend_from_slice(&offset.to_le_bytes());
+    }
+    out.extend_from_slice(&data);
+    Ok(out)
+}
+
+// A cell header is width, height, row stride, the transparent border
+// trimmed from each side (left, right, top, bottom) and flags, all u16.
+// The trimmed rows follow, then the mask rows if there is a mask.
+fn encode_cell(image: &Image, out: &mut Vec<u8>) -> Result<()> {
+    let has_mask = image.has_mask();
+    let (left, right, top, bottom) = if has_mask {
+        image.transparent_border()
+    } else {
+        (0, 0, 0, 0)
+    };
+    let width = image.width - left - right;
+    let height = image.height - top - bottom;
+    let trimmed = image.crop(left, top, width, height);
+    let stride = width.div_ceil(8);
+    let flags = if has_mask && trimmed.has_mask() { CELL_HAS_MASK } else { 0 };
+    for value in [width, height, stride, left, right, top, bottom].iter() {
+        out.extend_from_slice(&to_u16(*value, "image dimension")?.to_le_bytes());
+    }
+    out.extend_from_slice(&flags.to_le_bytes());
+    pack(&trimmed.white, width, height, stride, out);
+    if flags != 0 {
+        pack(&trimmed.opaque, width, height, stride, out);
+    }
+    Ok(())
+}
+
+// Rows of `stride` bytes, most significant bit first, padding bits set.
+fn pack(bits: &[bool], width: usize, height: usize, stride: usize, out: &mut Vec<u8>) {
+    for y in 0..height {
+        for byte in 0..stride {
+            let mut value = 0xFFu8;
+            for bit in 0..8 {
+                let x = byte * 8 + bit;
+                if x < width && !bits[y * width + x] {
+                    value &= !(0x80 >> bit);
+                }
+            }
+            out.push(value);
+        }
+    }
+}
+
+fn to_u16(value: usize, what: &str) -> Result<u16> {
+    u16::try_from(value).ok().with_context(|| format!("{} {} too large", what, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::tests::image;
+
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn packs_rows_with_padding_set() {
+        let mut out = Vec::new();
+        let bits = [true, false, true, false, true, false, true, false, true, false];
+        pack(&bits, 10, 1, 2, &mut out);
+        assert_eq!(out, [0b1010_1010, 0b1011_1111]);
+        out.clear();
+        pack(&[false; 20], 10, 2, 2, &mut out);
+        assert_eq!(out, [0x00, 0x3F, 0x00, 0x3F]);
+    }
+
+    // pdc is not available to produce reference files, so the expected bytes
+    // are written out by hand from the format described above.
+    #[test]
+    fn pdi_layout() {
+        let sprite = image(&[
+            ".o#o#o#o#.",
+            "ooooooooo.",
+        ]);
+        let mut expected = b"Playdate IMG".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 0]);
+        // 9x2 after trimming the right column, stride 2, masked.
+        expected.extend(u16s(&[9, 2, 2, 0, 1, 0, 0, 3]));
+        expected.extend_from_slice(&[0b0010_1010, 0b1111_1111, 0b0000_0000, 0b0111_1111]);
+        expected.extend_from_slice(&[0b0111_1111, 0b1111_1111, 0b1111_1111, 0b1111_1111]);
+        assert_eq!(encode_pdi(&sprite).unwrap(), expected);
+    }
+
+    #[test]
+    fn opaque_images_have_no_mask() {
+        let mut expected = b"Playdate IMG".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 0]);
+        expected.extend(u16s(&[3, 1, 1, 0, 0, 0, 0, 0]));
+        expected.push(0b1011_1111);
+        assert_eq!(encode_pdi(&image(&["#o#"])).unwrap(), expected);
+    }
+
+    #[test]
+    fn pdt_layout() {
+        let cells = [image(&["#"]), image(&[".o", ".."]), image(&["o"])];
+        let mut expected = b"Playdate IMT".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 0]);
+        expected.extend(u16s(&[3, 2]));
+        // Cell offsets from the end of this table: each cell is a 16 byte
+        // header and its rows.
+        expected.extend_from_slice(&[0, 0, 0, 0, 17, 0, 0, 0, 34, 0, 0, 0]);
+        expected.extend(u16s(&[1, 1, 1, 0, 0, 0, 0, 0]));
+        expected.push(0b1111_1111);
+        // Trimmed down to the single opaque pixel, which leaves no mask.
+        expected.extend(u16s(&[1, 1, 1, 1, 0, 0, 1, 0]));
+        expected.push(0b0111_1111);
+        expected.extend(u16s(&[1, 1, 1, 0, 0, 0, 0, 0]));
+        expected.push(0b0111_1111);
+        assert_eq!(encode_pdt(&cells, 2).unwrap(), expected);
+    }
+
+    #[test]
+    fn fully_transparent_cells_are_empty() {
+        let mut out = Vec::new();
+        encode_cell(&image(&["..", ".."]), &mut out).unwrap();
+        assert_eq!(out, u16s(&[0, 0, 0, 2, 0, 2, 0, 0]));
+    }
+
+    #[test]
+    fn rejects_empty_tables() {
+        assert!(encode_pdt(&[], 1).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{Result, ensure};
+
+// A 1-bit image as the Playdate stores it: each pixel is black or white,
+// and transparent pixels are cleared in the mask.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub white: Vec<bool>,
+    pub opaque: Vec<bool>,
+}
+
+impl Image {
+    // Pixels brighter than mid-gray become white; pixels less than half
+    // opaque become transparent.
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Self {
+        assert!(rgba.len() >= width * height * 4, "RGBA buffer too small");
+        let mut white = Vec::with_capacity(width * height);
+        let mut opaque = Vec::with_capacity(width * height);
+        for px in rgba.chunks(4).take(width * height) {
+            let luma = (px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) / 1000;
+            let visible = px[3] >= 128;
+            white.push(visible && luma >= 128);
+            opaque.push(visible);
+        }
+        Self { width, height, white, opaque }
+    }
+
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.white.iter().zip(&self.opaque).flat_map(|(&white, &opaque)| {
+            let level = if white { 255 } else { 0 };
+            vec![level, level, level, if opaque { 255 } else { 0 }]
+        }).collect()
+    }
+
+    pub fn has_mask(&self) -> bool {
+        self.opaque.iter().any(|&opaque| !opaque)
+    }
+
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        assert!(x + width <= self.width && y + height <= self.height, "crop out of bounds");
+        let mut white = Vec::with_capacity(width * height);
+        let mut opaque = Vec::with_capacity(width * height);
+        for row in y..y + height {
+            let start = row * self.width + x;
+            white.extend_from_slice(&self.white[start..start + width]);
+            opaque.extend_from_slice(&self.opaque[start..start + width]);
+        }
+        Self { width, height, white, opaque }
+    }
+
+    // Splits a grid sheet into cells, left to right then top to bottom.
+    pub fn cells(&self, width: usize, height: usize) -> Result<Vec<Self>> {
+        ensure!(width > 0 && height > 0, "cell size must be non-zero");
+        ensure!(self.width.is_multiple_of(width) && self.height.is_multiple_of(height),
+                "{}x{} image is not a whole number of {}x{} cells",
+                self.width, self.height, width, height);
+        let mut cells = Vec::new();
+        for y in (0..self.height).step_by(height) {
+            for x in (0..self.width).step_by(width) {
+                cells.push(self.crop(x, y, width, height));
+            }
+        }
+        Ok(cells)
+    }
+
+    // Fully transparent columns and rows on each side, as
+    // (left, right, top, bottom). An empty image clips to nothing.
+    pub fn transparent_border(&self) -> (usize, usize, usize, usize) {
+        let column = |x: usize| (0..self.height).all(|y| !self.opaque[y * self.width + x]);
+        let row = |y: usize| (0..self.width).all(|x| !self.opaque[y * self.width + x]);
+        let left = (0..self.width).take_while(|&x| column(x)).count();
+        if left == self.width {
+            return (self.width, 0, self.height, 0);
+        }
+        let right = (0..self.width).rev().take_while(|&x| column(x)).count();
+        let top = (0..self.height).take_while(|&y| row(y)).count();
+        let bottom = (0..self.height).rev().take_while(|&y| row(y)).count();
+        (left, right, top, bottom)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // `#` white, `o` black, `.` transparent.
+    pub(crate) fn image(rows: &[&str]) -> Image {
+        let width = rows[0].len();
+        let pixels = rows.concat();
+        Image {
+            width,
+            height: rows.len(),
+            white: pixels.chars().map(|c| c == '#').collect(),
+            opaque: pixels.chars().map(|c| c != '.').collect(),
+        }
+    }
+
+    #[test]
+    fn thresholds_luma_and_alpha() {
+        let rgba = [255, 255, 255, 255, 0, 0, 0, 255, 200, 200, 200, 100, 0, 255, 0, 128];
+        let image = Image::from_rgba(4, 1, &rgba);
+        assert_eq!(image.white, [true, false, false, true]);
+        assert_eq!(image.opaque, [true, true, false, true]);
+    }
+
+    #[test]
+    fn finds_the_transparent_border() {
+        let sprite = image(&[
+            ".....",
+            ".#o..",
+            "..o..",
+            ".....",
+        ]);
+        assert_eq!(sprite.transparent_border(), (1, 2, 1, 1));
+        assert_eq!(image(&["#o", "o#"]).transparent_border(), (0, 0, 0, 0));
+        assert_eq!(image(&["...", "..."]).transparent_border(), (3, 0, 2, 0));
+    }
+
+    #[test]
+    fn splits_cells_in_reading_order() {
+        let sheet = image(&[
+            "#.o.",
+            "..#o",
+        ]);
+        let cells = sheet.cells(2, 1).unwrap();
+        assert_eq!(cells, [image(&["#."]), image(&["o."]), image(&[".."]), image(&["#o"])]);
+        assert!(sheet.cells(3, 1).is_err());
+        assert!(sheet.cells(0, 1).is_err());
+    }
+}
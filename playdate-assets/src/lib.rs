@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context, bail};
+
+pub mod font;
+pub mod format;
+pub mod image;
+pub mod load;
+
+pub use crate::image::Image;
+
+// How pdc names image tables: `name-table-W-H` is a W x H grid sheet.
+// Returns the output name and the cell size, if any.
+pub fn parse_name(stem: &str) -> (&str, Option<(usize, usize)>) {
+    let mut parts = stem.rsplitn(4, '-');
+    if let (Some(h), Some(w), Some("table"), Some(name)) =
+        (parts.next(), parts.next(), parts.next(), parts.next()) {
+        if let (Ok(w), Ok(h)) = (w.parse(), h.parse()) {
+            return (name, Some((w, h)));
+        }
+    }
+    (stem, None)
+}
+
+// Converts one PNG or GIF into `out_dir`, returning the path written.
+// Grid-named sheets and animated GIFs become `.pdt` tables; everything
+// else becomes a `.pdi`.
+pub fn convert(input: &Path, out_dir: &Path) -> Result<PathBuf> {
+    let stem = input.file_stem().and_then(|s| s.to_str())
+        .with_context(|| format!("bad file name {}", input.display()))?;
+    let ext = input.extension().and_then(|s| s.to_str()).unwrap_or("").to_ascii_lowercase();
+    let (name, grid) = parse_name(stem);
+    let mut frames = match ext.as_str() {
+        "png" => vec![load::load_png(input)?],
+        "gif" => load::load_gif(input)?,
+        _ => bail!("unsupported image {}", input.display()),
+    };
+    let (bytes, ext) = match (grid, frames.len()) {
+        (Some((w, h)), 1) => {
+            let sheet = frames.remove(0);
+            let cells = sheet.cells(w, h)?;
+            (format::encode_pdt(&cells, sheet.width / w)?, "pdt")
+        }
+        (Some(_), _) => bail!("grid image table {} has more than one frame", input.display()),
+        (None, 1) => (format::encode_pdi(&frames[0])?, "pdi"),
+        (None, n) => (format::encode_pdt(&frames, n)?, "pdt"),
+    };
+    fs::create_dir_all(out_dir)?;
+    let output = out_dir.join(name).with_extension(ext);
+    fs::write(&output, bytes).with_context(|| format!("failed to write {}", output.display()))?;
+    Ok(output)
+}
+
+pub struct FontOptions {
+    pub size: f32,
+    pub charset: Vec<char>,
+    pub tracking: i32,
+    pub columns: usize,
+}
+
+impl Default for FontOptions {
+    fn default() -> Self {
+        Self { size: 16.0, charset: font::ASCII.collect(), tracking: 0, columns: 16 }
+    }
+}
+
+// Rasterizes a `.ttf`/`.otf` or reads a `.bdf` into `<name>.fnt` and its
+// `<name>-table-W-H.png` glyph sheet in `out_dir`, ready for pdc. Returns
+// both paths.
+pub fn convert_font(input: &Path, name: &str, out_dir: &Path, options: &FontOptions)
+                    -> Result<(PathBuf, PathBuf)> {
+    let ext = input.extension().and_then(|s| s.to_str()).unwrap_or("").to_ascii_lowercase();
+    let data = fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
+    let font = match ext.as_str() {
+        "ttf" | "otf" => font::rasterize_ttf(&data, options.size, &options.charset,
+                                             options.tracking)?,
+        "bdf" => font::parse_bdf(&String::from_utf8_lossy(&data), &options.charset,
+                                 options.tracking)?,
+        _ => bail!("unsupported font {}", input.display()),
+    };
+    fs::create_dir_all(out_dir)?;
+    let fnt = out_dir.join(format!("{}.fnt", name));
+    fs::write(&fnt, font.to_fnt()).with_context(|| format!("failed to write {}", fnt.display()))?;
+    let sheet = out_dir.join(font.sheet_name(name));
+    write_png(&font.sheet(options.columns), &sheet)?;
+    Ok((fnt, sheet))
+}
+
+pub fn write_png(image: &Image, path: &Path) -> Result<()> {
+    let file = fs::File::create(path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    let mut encoder = png::Encoder::new(file, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&image.to_rgba())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_table_names() {
+        assert_eq!(parse_name("hero-table-16-32"), ("hero", Some((16, 32))));
+        assert_eq!(parse_name("big-hero-table-8-8"), ("big-hero", Some((8, 8))));
+    }
+
+    #[test]
+    fn other_names_are_kept_whole() {
+        assert_eq!(parse_name("hero"), ("hero", None));
+        assert_eq!(parse_name("hero-8-8"), ("hero-8-8", None));
+        assert_eq!(parse_name("table-8-8"), ("table-8-8", None));
+        assert_eq!(parse_name("hero-tiles-8-8"), ("hero-tiles-8-8", None));
+        assert_eq!(parse_name("hero-table-8-x"), ("hero-table-8-x", None));
+        assert_eq!(parse_name("hero-table--8"), ("hero-table--8", None));
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::path::Path;
+use anyhow::{Result, Context, bail};
+use crate::image::Image;
+
+pub fn load_png(path: &Path) -> Result<Image> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let pixels = &buf[..info.buffer_size()];
+    let rgba: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => {
+            pixels.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect()
+        }
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&p| vec![p, p, p, 255]).collect(),
+        png::ColorType::Indexed => bail!("unexpanded indexed PNG {}", path.display()),
+    };
+    Ok(Image::from_rgba(width, height, &rgba))
+}
+
+// Every frame of the animation, composited onto the logical screen the way
+// a viewer would show it.
+pub fn load_gif(path: &Path) -> Result<Vec<Image>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(file)?;
+    let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+    let mut canvas = vec![0u8; width * height * 4];
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame()? {
+        let previous = canvas.clone();
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        let (fw, fh) = (frame.width as usize, frame.height as usize);
+        for y in 0..fh.min(height.saturating_sub(top)) {
+            for x in 0..fw.min(width.saturating_sub(left)) {
+                let src = &frame.buffer[(y * fw + x) * 4..][..4];
+                if src[3] == 0 {
+                    continue;
+                }
+                let dst = ((top + y) * width + left + x) * 4;
+                canvas[dst..dst + 4].copy_from_slice(src);
+            }
+        }
+        frames.push(Image::from_rgba(width, height, &canvas));
+        match frame.dispose {
+            gif::DisposalMethod::Background => {
+                for y in top..(top + fh).min(height) {
+                    for x in left..(left + fw).min(width) {
+                        let dst = (y * width + x) * 4;
+                        canvas[dst..dst + 4].copy_from_slice(&[0; 4]);
+                    }
+                }
+            }
+            gif::DisposalMethod::Previous => canvas = previous,
+            _ => {}
+        }
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use std::path::PathBuf;
+    use crate::image::tests::image;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("playdate-assets-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let sprite = image(&[
+            ".#o",
+            "o#.",
+        ]);
+        let path = temp_path("round-trip.png");
+        crate::write_png(&sprite, &path).unwrap();
+        let loaded = load_png(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), sprite);
+    }
+
+    // Palette: 0 transparent, 1 white, 2 black.
+    fn frame(left: u16, top: u16, width: u16, buffer: &[u8],
+             dispose: gif::DisposalMethod) -> gif::Frame<'_> {
+        gif::Frame {
+            left,
+            top,
+            width,
+            height: buffer.len() as u16 / width,
+            buffer: Cow::Borrowed(buffer),
+            transparent: Some(0),
+            dispose,
+            ..gif::Frame::default()
+        }
+    }
+
+    #[test]
+    fn gif_frames_are_composited() {
+        let path = temp_path("frames.gif");
+        {
+            let file = File::create(&path).unwrap();
+            let palette = [0, 0, 0, 255, 255, 255, 0, 0, 0];
+            let mut encoder = gif::Encoder::new(file, 2, 2, &palette).unwrap();
+            let keep = gif::DisposalMethod::Keep;
+            encoder.write_frame(&frame(0, 0, 2, &[1, 2, 2, 1], keep)).unwrap();
+            // A transparent pixel leaves the previous one showing.
+            encoder.write_frame(&frame(0, 1, 2, &[0, 2], gif::DisposalMethod::Background))
+                .unwrap();
+            encoder.write_frame(&frame(1, 0, 1, &[1], keep)).unwrap();
+        }
+        let frames = load_gif(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(frames.unwrap(), [
+            image(&["#o", "o#"]),
+            image(&["#o", "oo"]),
+            // The second frame's rows were cleared when it was disposed.
+            image(&["##", ".."]),
+        ]);
+    }
+}
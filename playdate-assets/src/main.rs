@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "usage: playdate-assets [-o <out dir>] <image.png|image.gif>...";
+
+fn main() {
+    let mut out_dir = PathBuf::from(".");
+    let mut inputs = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(dir) => out_dir = PathBuf::from(dir),
+                None => usage(),
+            },
+            "-h" | "--help" => usage(),
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+    if inputs.is_empty() {
+        usage();
+    }
+    let mut failed = false;
+    for input in inputs {
+        match playdate_assets::convert(&input, &out_dir) {
+            Ok(output) => println!("{} -> {}", input.display(), output.display()),
+            Err(err) => {
+                eprintln!("{}: {:#}", input.display(), err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
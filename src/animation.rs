@@ -0,0 +1,495 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+use anyhow::{Result, Error, anyhow, ensure};
+use crate::Playdate;
+use crate::graphics::{BitmapFlip, BitmapRef, BitmapTable};
+use crate::json::{Value, ValueType, Visitor};
+use crate::sprite::Sprite;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Loop,
+    PingPong,
+    Once,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub index: usize,
+    pub duration: u32,
+}
+
+// Plays frames of a `BitmapTable`, each shown for its own duration in
+// milliseconds. The completion callback runs at the end of every cycle,
+// or once when a `Mode::Once` animation reaches its last frame.
+pub struct Animation<'a> {
+    table: &'a BitmapTable,
+    frames: Vec<Frame>,
+    mode: Mode,
+    position: usize,
+    forward: bool,
+    elapsed: u32,
+    playing: bool,
+    finished: bool,
+    on_complete: Option<Box<dyn FnMut()>>,
+}
+
+impl<'a> Animation<'a> {
+    // Table indices `range`, `duration` milliseconds each.
+    pub fn new(table: &'a BitmapTable, range: Range<usize>, duration: u32) -> Self {
+        Self::from_frames(table, range.map(|index| Frame { index, duration }).collect())
+    }
+
+    pub fn from_frames(table: &'a BitmapTable, frames: Vec<Frame>) -> Self {
+        Self {
+            table,
+            frames,
+            mode: Mode::Loop,
+            position: 0,
+            forward: true,
+            elapsed: 0,
+            playing: true,
+            finished: false,
+            on_complete: None,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    // Overrides frame durations in order; extra durations are ignored.
+    pub fn with_durations(mut self, durations: &[u32]) -> Self {
+        for (frame, &duration) in self.frames.iter_mut().zip(durations) {
+            frame.duration = duration;
+        }
+        self
+    }
+
+    pub fn on_complete<F: FnMut() + 'static>(mut self, callback: F) -> Self {
+        self.on_complete = Some(Box::new(callback));
+        self
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing && !self.finished
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn reset(&mut self) {
+        self.position = 0;
+        self.forward = true;
+        self.elapsed = 0;
+        self.finished = false;
+    }
+
+    // Position in `frames()`, not the table index.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: usize) {
+        self.position = position.min(self.frames.len().saturating_sub(1));
+        self.elapsed = 0;
+    }
+
+    pub fn index(&self) -> Option<usize> {
+        self.frames.get(self.position).map(|frame| frame.index)
+    }
+
+    pub fn bitmap(&self) -> Result<BitmapRef<'a>> {
+        let index = self.index().ok_or_else(|| anyhow!("animation has no frames"))?;
+        self.table.get_bitmap(index)
+    }
+
+    // Advances by `dt_ms` and returns whether the frame changed.
+    pub fn update(&mut self, dt_ms: u32) -> bool {
+        if !self.is_playing() || self.frames.is_empty() {
+            return false;
+        }
+        let start = self.position;
+        self.elapsed += dt_ms;
+        loop {
+            let duration = self.frames[self.position].duration.max(1);
+            if self.elapsed < duration {
+                break;
+            }
+            self.elapsed -= duration;
+            if !self.advance() {
+                self.elapsed = 0;
+                break;
+            }
+        }
+        self.position != start
+    }
+
+    fn advance(&mut self) -> bool {
+        let last = self.frames.len() - 1;
+        match self.mode {
+            Mode::Loop => {
+                if self.position == last {
+                    self.position = 0;
+                    self.complete();
+                } else {
+                    self.position += 1;
+                }
+            }
+            Mode::Once => {
+                if self.position == last {
+                    self.finished = true;
+                    self.complete();
+                    return false;
+                }
+                self.position += 1;
+            }
+            Mode::PingPong => {
+                if last == 0 {
+                    self.complete();
+                } else if self.forward {
+                    if self.position == last {
+                        self.forward = false;
+                        self.position -= 1;
+                    } else {
+                        self.position += 1;
+                    }
+                } else {
+                    self.position -= 1;
+                    if self.position == 0 {
+                        self.forward = true;
+                        self.complete();
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn complete(&mut self) {
+        if let Some(callback) = self.on_complete.as_mut() {
+            callback();
+        }
+    }
+
+    pub fn apply(&self, sprite: &mut Sprite<'a>, flip: BitmapFlip) -> Result<()> {
+        let index = self.index().ok_or_else(|| anyhow!("animation has no frames"))?;
+        sprite.set_table_image(self.table, index, flip)
+    }
+
+    // Advances the animation and, when the frame changed, shows it on
+    // `sprite`.
+    pub fn update_sprite(&mut self, dt_ms: u32, sprite: &mut Sprite<'a>, flip: BitmapFlip) -> Result<()> {
+        if self.update(dt_ms) {
+            self.apply(sprite, flip)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+    PingPong,
+    PingPongReverse,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    pub direction: Direction,
+}
+
+// Frame durations and tags from an Aseprite sprite sheet exported as JSON
+// (`--data`, with `--list-tags`), in either the array or hash layout. The
+// sheet image itself is loaded as the `BitmapTable`.
+pub struct AsepriteSheet {
+    pub durations: Vec<u32>,
+    pub tags: Vec<Tag>,
+}
+
+impl AsepriteSheet {
+    pub fn load(path: &str) -> Result<Self> {
+        let mut visitor = AsepriteVisitor::default();
+        Playdate::get_json().decode_file(path, &mut visitor)?;
+        if let Some(error) = visitor.error {
+            return Err(Error::msg(error));
+        }
+        Ok(Self { durations: visitor.durations, tags: visitor.tags })
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&Tag> {
+        self.tags.iter().find(|tag| tag.name == name)
+    }
+
+    // Every frame in sheet order, looping.
+    pub fn animation<'a>(&self, table: &'a BitmapTable) -> Animation<'a> {
+        Animation::from_frames(table, self.frames(0..self.durations.len()))
+    }
+
+    // The frames of tag `name` in its direction. Ping-pong tags play in
+    // `Mode::PingPong`; the others loop.
+    pub fn tag_animation<'a>(&self, table: &'a BitmapTable, name: &str) -> Result<Animation<'a>> {
+        let tag = self.tag(name).ok_or_else(|| anyhow!("no Aseprite tag {}", name))?;
+        ensure!(tag.from <= tag.to && tag.to < self.durations.len(),
+                "Aseprite tag {} is out of range", name);
+        let mut frames = self.frames(tag.from..tag.to + 1);
+        if let Direction::Reverse | Direction::PingPongReverse = tag.direction {
+            frames.reverse();
+        }
+        let mode = match tag.direction {
+            Direction::PingPong | Direction::PingPongReverse => Mode::PingPong,
+            _ => Mode::Loop,
+        };
+        Ok(Animation::from_frames(table, frames).with_mode(mode))
+    }
+
+    fn frames(&self, range: Range<usize>) -> Vec<Frame> {
+        range.map(|index| Frame { index, duration: self.durations[index] }).collect()
+    }
+}
+
+#[derive(Default)]
+struct AsepriteVisitor {
+    stack: Vec<String>,
+    key: String,
+    durations: Vec<u32>,
+    tags: Vec<Tag>,
+    tag: Option<Tag>,
+    error: Option<String>,
+}
+
+impl AsepriteVisitor {
+    fn parent_is(&self, key: &str) -> bool {
+        self.stack.len() >= 2 && self.stack[self.stack.len() - 2] == key
+    }
+}
+
+impl Visitor for AsepriteVisitor {
+    fn will_decode_sublist(&mut self, _name: &str, kind: ValueType) {
+        let key = core::mem::take(&mut self.key);
+        self.stack.push(key);
+        if kind == ValueType::kJSONTable && self.parent_is("frameTags") {
+            self.tag = Some(Tag {
+                name: String::new(),
+                from: 0,
+                to: 0,
+                direction: Direction::Forward,
+            });
+        }
+    }
+
+    fn should_decode_table_value(&mut self, key: &str) -> bool {
+        self.key = String::from(key);
+        true
+    }
+
+    fn should_decode_array_value(&mut self, _pos: i32) -> bool {
+        self.key.clear();
+        true
+    }
+
+    fn table_value(&mut self, key: &str, value: Value) {
+        if self.parent_is("frames") && key == "duration" {
+            if let Value::Integer(duration) = value {
+                self.durations.push(duration.max(0) as u32);
+            }
+        } else if self.parent_is("frameTags") {
+            if let Some(tag) = self.tag.as_mut() {
+                match (key, value) {
+                    ("name", Value::String(name)) => tag.name = String::from(name),
+                    ("from", Value::Integer(from)) => tag.from = from.max(0) as usize,
+                    ("to", Value::Integer(to)) => tag.to = to.max(0) as usize,
+                    ("direction", Value::String(direction)) => {
+                        tag.direction = match direction {
+                            "reverse" => Direction::Reverse,
+                            "pingpong" => Direction::PingPong,
+                            "pingpong_reverse" => Direction::PingPongReverse,
+                            _ => Direction::Forward,
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn did_decode_sublist(&mut self, _name: &str, _kind: ValueType) {
+        if self.parent_is("frameTags") {
+            if let Some(tag) = self.tag.take() {
+                self.tags.push(tag);
+            }
+        }
+        self.stack.pop();
+    }
+
+    fn decode_error(&mut self, error: &str, line: i32) {
+        self.error = Some(alloc::format!("{} at line {}", error, line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use core::cell::Cell;
+    use core::mem::ManuallyDrop;
+    use crate::json::tests::drive;
+
+    // Only frame bookkeeping is tested, so the table is never touched and
+    // must not be freed.
+    fn table() -> ManuallyDrop<BitmapTable> {
+        ManuallyDrop::new(unsafe { core::mem::zeroed() })
+    }
+
+    // Counts completions.
+    fn counted(animation: Animation<'_>) -> (Animation<'_>, Rc<Cell<u32>>) {
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
+        (animation.on_complete(move || counter.set(counter.get() + 1)), count)
+    }
+
+    fn positions(animation: &mut Animation, dt_ms: u32, steps: usize) -> Vec<usize> {
+        (0..steps).map(|_| {
+            animation.update(dt_ms);
+            animation.position()
+        }).collect()
+    }
+
+    #[test]
+    fn update_steps_across_several_frames() {
+        let table = table();
+        let frames = vec![Frame { index: 4, duration: 100 }, Frame { index: 5, duration: 50 },
+                          Frame { index: 6, duration: 200 }];
+        let (mut animation, count) = counted(Animation::from_frames(&table, frames));
+        assert!(!animation.update(99));
+        // 151ms in all carries through frames 0 and 1 into 2 with 1ms to spare.
+        assert!(animation.update(52));
+        assert_eq!((animation.position(), animation.index()), (2, Some(6)));
+        assert!(!animation.update(198));
+        assert!(animation.update(1));
+        assert_eq!(animation.position(), 0);
+        assert_eq!(count.get(), 1);
+        // Two whole cycles in one update.
+        animation.update(700);
+        assert_eq!((animation.position(), count.get()), (0, 3));
+    }
+
+    #[test]
+    fn ping_pong_reverses_at_both_ends() {
+        let table = table();
+        let (animation, count) = counted(Animation::new(&table, 0..3, 10));
+        let mut animation = animation.with_mode(Mode::PingPong);
+        assert_eq!(positions(&mut animation, 10, 8), [1, 2, 1, 0, 1, 2, 1, 0]);
+        assert_eq!(count.get(), 2);
+        // A single frame completes every cycle without moving.
+        let (animation, count) = counted(Animation::new(&table, 0..1, 10));
+        let mut animation = animation.with_mode(Mode::PingPong);
+        assert_eq!(positions(&mut animation, 10, 3), [0, 0, 0]);
+        assert_eq!(count.get(), 3);
+    }
+
+    #[test]
+    fn once_completes_exactly_once() {
+        let table = table();
+        let (animation, count) = counted(Animation::new(&table, 0..2, 10));
+        let mut animation = animation.with_mode(Mode::Once);
+        assert!(animation.update(1000));
+        assert_eq!(animation.position(), 1);
+        assert!(animation.is_finished() && !animation.is_playing());
+        assert_eq!(count.get(), 1);
+        assert!(!animation.update(1000));
+        assert_eq!(count.get(), 1);
+        animation.reset();
+        assert_eq!(positions(&mut animation, 10, 3), [1, 1, 1]);
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn paused_and_zero_length_frames() {
+        let table = table();
+        let mut animation = Animation::new(&table, 0..2, 0);
+        // Zero durations count as one millisecond.
+        assert!(animation.update(1));
+        assert_eq!(animation.position(), 1);
+        animation.pause();
+        assert!(!animation.update(100));
+        assert!(!Animation::from_frames(&table, Vec::new()).update(100));
+    }
+
+    fn sheet(json: &str) -> AsepriteSheet {
+        let mut visitor = AsepriteVisitor::default();
+        drive(json, &mut visitor);
+        assert_eq!(visitor.error, None);
+        AsepriteSheet { durations: visitor.durations, tags: visitor.tags }
+    }
+
+    const TAGS: &str = "\"meta\": {\"size\": {\"w\": 48, \"h\": 16}, \"frameTags\": [
+        {\"name\": \"walk\", \"from\": 0, \"to\": 1, \"direction\": \"forward\"},
+        {\"name\": \"idle\", \"from\": 1, \"to\": 2, \"direction\": \"pingpong_reverse\"}]}";
+
+    #[test]
+    fn aseprite_array_and_hash_layouts_agree() {
+        let array = sheet(&alloc::format!("{{\"frames\": [
+            {{\"frame\": {{\"x\": 0, \"w\": 16}}, \"duration\": 100}},
+            {{\"frame\": {{\"x\": 16, \"w\": 16}}, \"duration\": 80}},
+            {{\"frame\": {{\"x\": 32, \"w\": 16}}, \"duration\": 120}}], {}}}", TAGS));
+        let hash = sheet(&alloc::format!("{{\"frames\": {{
+            \"hero 0.ase\": {{\"frame\": {{\"x\": 0, \"w\": 16}}, \"duration\": 100}},
+            \"hero 1.ase\": {{\"frame\": {{\"x\": 16, \"w\": 16}}, \"duration\": 80}},
+            \"hero 2.ase\": {{\"frame\": {{\"x\": 32, \"w\": 16}}, \"duration\": 120}}}}, {}}}",
+            TAGS));
+        for sheet in [array, hash] {
+            assert_eq!(sheet.durations, [100, 80, 120]);
+            assert_eq!(sheet.tags, [
+                Tag { name: String::from("walk"), from: 0, to: 1, direction: Direction::Forward },
+                Tag { name: String::from("idle"), from: 1, to: 2,
+                      direction: Direction::PingPongReverse },
+            ]);
+        }
+    }
+
+    #[test]
+    fn tag_animations_follow_their_direction() {
+        let table = table();
+        let sheet = AsepriteSheet {
+            durations: vec![100, 80, 120],
+            tags: vec![Tag { name: String::from("back"), from: 0, to: 2,
+                             direction: Direction::Reverse },
+                       Tag { name: String::from("bad"), from: 2, to: 3,
+                             direction: Direction::Forward }],
+        };
+        let animation = sheet.tag_animation(&table, "back").unwrap();
+        assert_eq!(animation.frames(), [Frame { index: 2, duration: 120 },
+                                        Frame { index: 1, duration: 80 },
+                                        Frame { index: 0, duration: 100 }]);
+        assert_eq!(animation.mode(), Mode::Loop);
+        assert!(sheet.tag_animation(&table, "bad").is_err());
+        assert!(sheet.tag_animation(&table, "none").is_err());
+        assert_eq!(sheet.animation(&table).frames().len(), 3);
+    }
+}
@@ -0,0 +1,231 @@
+use core::slice::{Chunks, ChunksMut};
+use core::iter::Take;
+
+// A 1-bit pixel buffer laid out like the Playdate frame buffer and bitmap
+// data: rows of `rowbytes` bytes, most significant bit first. A set bit is
+// white in image data and opaque in a mask.
+pub struct BitPlane<B> {
+    buf: B,
+    width: usize,
+    height: usize,
+    rowbytes: usize,
+}
+
+impl<B: AsRef<[u8]>> BitPlane<B> {
+    pub fn new(buf: B, width: usize, height: usize, rowbytes: usize) -> Self {
+        assert!(rowbytes * 8 >= width, "rowbytes too small for width");
+        assert!(buf.as_ref().len() >= rowbytes * height, "buffer too small for bit plane");
+        Self { buf, width, height, rowbytes }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn rowbytes(&self) -> usize {
+        self.rowbytes
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf.as_ref()[..self.rowbytes * self.height]
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        if !self.contains(x, y) {
+            return false;
+        }
+        let byte = self.buf.as_ref()[y as usize * self.rowbytes + x as usize / 8];
+        byte & (0x80 >> (x as usize % 8)) != 0
+    }
+
+    pub fn row(&self, y: usize) -> &[u8] {
+        let start = y * self.rowbytes;
+        &self.buf.as_ref()[start..start + self.rowbytes]
+    }
+
+    pub fn rows(&self) -> Take<Chunks<'_, u8>> {
+        self.buf.as_ref().chunks(self.rowbytes).take(self.height)
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> BitPlane<B> {
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        let len = self.rowbytes * self.height;
+        &mut self.buf.as_mut()[..len]
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, value: bool) {
+        if !self.contains(x, y) {
+            return;
+        }
+        let bit = 0x80 >> (x as usize % 8);
+        let byte = &mut self.buf.as_mut()[y as usize * self.rowbytes + x as usize / 8];
+        if value {
+            *byte |= bit;
+        } else {
+            *byte &= !bit;
+        }
+    }
+
+    pub fn fill(&mut self, value: bool) {
+        let fill = if value { 0xFF } else { 0x00 };
+        for byte in self.as_bytes_mut() {
+            *byte = fill;
+        }
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        let start = y * self.rowbytes;
+        let end = start + self.rowbytes;
+        &mut self.buf.as_mut()[start..end]
+    }
+
+    pub fn rows_mut(&mut self) -> Take<ChunksMut<'_, u8>> {
+        let (rowbytes, height) = (self.rowbytes, self.height);
+        self.buf.as_mut().chunks_mut(rowbytes).take(height)
+    }
+
+    // Copies every pixel of `src` with its top-left corner at (x, y),
+    // clipped to this plane. Works a source byte at a time rather than
+    // per pixel.
+    pub fn blit<C: AsRef<[u8]>>(&mut self, src: &BitPlane<C>, x: i32, y: i32) {
+        let width = self.width as i32;
+        let src_bytes = (src.width + 7) / 8;
+        for sy in 0..src.height {
+            let dy = y + sy as i32;
+            if dy < 0 || dy >= self.height as i32 {
+                continue;
+            }
+            let src_row = src.row(sy);
+            let dst_row = self.row_mut(dy as usize);
+            for i in 0..src_bytes {
+                let dx = x + 8 * i as i32;
+                let valid = (src.width - 8 * i).min(8) as i32;
+                let lo = (-dx).max(0);
+                let hi = (width - dx).min(valid);
+                if lo >= hi {
+                    continue;
+                }
+                let mask = ((0xFFu16 >> lo) & (0xFF00u16 >> hi)) as u8;
+                let bits = src_row[i] & mask;
+                let shift = dx.rem_euclid(8) as u32;
+                let base = dx.div_euclid(8);
+                if base >= 0 {
+                    let byte = &mut dst_row[base as usize];
+                    *byte = (*byte & !(mask >> shift)) | (bits >> shift);
+                }
+                if shift != 0 && base + 1 >= 0 && ((base + 1) as usize) < dst_row.len() {
+                    let byte = &mut dst_row[(base + 1) as usize];
+                    let mask = ((mask as u16) << (8 - shift)) as u8;
+                    let bits = ((bits as u16) << (8 - shift)) as u8;
+                    *byte = (*byte & !mask) | bits;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    // 11x3, two bytes a row, so the last byte of each row is partial.
+    fn source() -> BitPlane<[u8; 6]> {
+        BitPlane::new([0b1011_0011, 0b1010_0000,
+                       0b0111_1111, 0b1110_0000,
+                       0b1000_0000, 0b0010_0000], 11, 3, 2)
+    }
+
+    // 20x5 with a spare byte per row, so padding past the width is visible.
+    fn target() -> BitPlane<Vec<u8>> {
+        BitPlane::new(vec![0xA5; 20], 20, 5, 4)
+    }
+
+    #[test]
+    fn get_and_set_address_msb_first() {
+        let mut plane = BitPlane::new([0u8; 4], 12, 2, 2);
+        plane.set(0, 0, true);
+        plane.set(9, 0, true);
+        plane.set(11, 1, true);
+        assert_eq!(plane.as_bytes(), &[0b1000_0000, 0b0100_0000, 0, 0b0001_0000]);
+        assert!(plane.get(9, 0) && !plane.get(8, 0));
+        plane.set(9, 0, false);
+        assert_eq!(plane.row(0), &[0b1000_0000, 0]);
+    }
+
+    #[test]
+    fn out_of_bounds_is_clear_and_ignored() {
+        let mut plane = BitPlane::new([0xFFu8; 4], 12, 2, 2);
+        for &(x, y) in &[(-1, 0), (0, -1), (12, 0), (0, 2)] {
+            assert!(!plane.get(x, y));
+            plane.set(x, y, false);
+        }
+        assert_eq!(plane.as_bytes(), &[0xFF; 4]);
+    }
+
+    #[test]
+    fn rows_and_fill_stop_at_the_height() {
+        let mut plane = BitPlane::new([0x0Fu8; 7], 8, 3, 2);
+        plane.fill(true);
+        assert_eq!(plane.rows().count(), 3);
+        assert!(plane.rows().all(|row| row == [0xFF, 0xFF]));
+        assert_eq!(plane.buf[6], 0x0F);
+        for row in plane.rows_mut() {
+            row[1] = 0;
+        }
+        assert_eq!(plane.as_bytes(), &[0xFF, 0, 0xFF, 0, 0xFF, 0]);
+    }
+
+    #[test]
+    fn blit_unaligned() {
+        let mut dst = BitPlane::new([0u8; 9], 24, 3, 3);
+        dst.blit(&source(), 3, 1);
+        assert_eq!(dst.row(0), &[0, 0, 0]);
+        // 1011_0011 101 shifted right by three.
+        assert_eq!(dst.row(1), &[0b0001_0110, 0b0111_0100, 0]);
+        assert_eq!(dst.row(2), &[0b0000_1111, 0b1111_1100, 0]);
+    }
+
+    #[test]
+    fn blit_clips_left_top_right_and_bottom() {
+        let mut dst = BitPlane::new([0u8; 4], 12, 2, 2);
+        dst.blit(&source(), -5, -1);
+        // Rows 1 and 2 of the source, from column 5.
+        assert_eq!(dst.row(0), &[0b1111_1100, 0]);
+        assert_eq!(dst.row(1), &[0b0000_0100, 0]);
+
+        let mut dst = BitPlane::new([0u8; 4], 12, 2, 2);
+        dst.blit(&source(), 6, 1);
+        // Only the first six columns of source row 0 fit.
+        assert_eq!(dst.row(0), &[0, 0]);
+        assert_eq!(dst.row(1), &[0b0000_0010, 0b1100_0000]);
+    }
+
+    #[test]
+    fn blit_matches_per_pixel_copy() {
+        let src = source();
+        for y in -4..=6 {
+            for x in -12..=22 {
+                let mut fast = target();
+                fast.blit(&src, x, y);
+                let mut slow = target();
+                for sy in 0..3 {
+                    for sx in 0..11 {
+                        slow.set(x + sx, y + sy, src.get(sx, sy));
+                    }
+                }
+                assert_eq!(fast.as_bytes(), slow.as_bytes(), "at ({}, {})", x, y);
+            }
+        }
+    }
+}
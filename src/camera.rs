@@ -0,0 +1,293 @@
+use crate::graphics::{Graphics, COLUMNS, ROWS};
+use crate::math;
+use crate::sprite::PDRect;
+
+// A view onto a larger world. `x`/`y` is the world position of the
+// screen's top-left corner; `apply` turns it into the draw offset, so
+// everything drawn afterwards is in world coordinates except sprites that
+// ignore the draw offset, which stay in screen coordinates.
+pub struct Camera2D {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    bounds: Option<PDRect>,
+    deadzone: PDRect,
+    lerp: f32,
+    trauma: f32,
+    max_shake: f32,
+    shake_decay: f32,
+    shake_frequency: f32,
+    time: f32,
+    shake: (f32, f32),
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Self::new(COLUMNS as f32, ROWS as f32)
+    }
+}
+
+impl Camera2D {
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width,
+            height,
+            bounds: None,
+            deadzone: PDRect { x: width / 2.0, y: height / 2.0, width: 0.0, height: 0.0 },
+            lerp: 1.0,
+            trauma: 0.0,
+            max_shake: 8.0,
+            shake_decay: 1.5,
+            shake_frequency: 25.0,
+            time: 0.0,
+            shake: (0.0, 0.0),
+        }
+    }
+
+    pub fn position(&self) -> (f32, f32) {
+        (self.x, self.y)
+    }
+
+    pub fn set_position(&mut self, x: f32, y: f32) {
+        self.x = x;
+        self.y = y;
+        self.clamp();
+    }
+
+    pub fn center_on(&mut self, x: f32, y: f32) {
+        self.set_position(x - self.width / 2.0, y - self.height / 2.0);
+    }
+
+    // The world area the view must stay inside. A world smaller than the
+    // view is centered.
+    pub fn set_bounds(&mut self, bounds: Option<PDRect>) {
+        self.bounds = bounds;
+        self.clamp();
+    }
+
+    // Area of the screen, relative to its top-left corner, the followed
+    // target can move in without the camera moving.
+    pub fn set_deadzone(&mut self, deadzone: PDRect) {
+        self.deadzone = deadzone;
+    }
+
+    // Fraction of the remaining distance covered each `follow`; 1 snaps.
+    pub fn set_lerp(&mut self, lerp: f32) {
+        self.lerp = lerp.max(0.0).min(1.0);
+    }
+
+    pub fn follow(&mut self, target_x: f32, target_y: f32) {
+        let (sx, sy) = (target_x - self.x, target_y - self.y);
+        let dz = self.deadzone;
+        let mut goal = (self.x, self.y);
+        if sx < dz.x {
+            goal.0 = target_x - dz.x;
+        } else if sx > dz.x + dz.width {
+            goal.0 = target_x - dz.x - dz.width;
+        }
+        if sy < dz.y {
+            goal.1 = target_y - dz.y;
+        } else if sy > dz.y + dz.height {
+            goal.1 = target_y - dz.y - dz.height;
+        }
+        self.x += (goal.0 - self.x) * self.lerp;
+        self.y += (goal.1 - self.y) * self.lerp;
+        self.clamp();
+    }
+
+    fn clamp(&mut self) {
+        if let Some(b) = self.bounds {
+            self.x = clamp_axis(self.x, b.x, b.width, self.width);
+            self.y = clamp_axis(self.y, b.y, b.height, self.height);
+        }
+    }
+
+    // Adds `amount` (0 to 1) of shake. The offset grows with the square of
+    // the accumulated shake, which decays by `decay` per second.
+    pub fn shake(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
+    pub fn set_shake(&mut self, max_offset: f32, decay: f32, frequency: f32) {
+        self.max_shake = max_offset;
+        self.shake_decay = decay;
+        self.shake_frequency = frequency;
+    }
+
+    pub fn update(&mut self, dt_ms: u32) {
+        let dt = dt_ms as f32 / 1000.0;
+        self.time += dt;
+        self.trauma = (self.trauma - self.shake_decay * dt).max(0.0);
+        let amount = self.trauma * self.trauma * self.max_shake;
+        let t = self.time * self.shake_frequency;
+        self.shake = (amount * noise(0x9E37_79B9, t), amount * noise(0x85EB_CA6B, t));
+    }
+
+    // The draw offset for the current position and shake.
+    pub fn offset(&self) -> (i32, i32) {
+        (-round(self.x + self.shake.0), -round(self.y + self.shake.1))
+    }
+
+    pub fn apply(&self, graphics: &Graphics) {
+        let (dx, dy) = self.offset();
+        graphics.set_draw_offset(dx, dy);
+    }
+
+    pub fn world_to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        let (dx, dy) = self.offset();
+        (x + dx as f32, y + dy as f32)
+    }
+
+    pub fn screen_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        let (dx, dy) = self.offset();
+        (x - dx as f32, y - dy as f32)
+    }
+
+    // Where to move a sprite to show it at world position (x, y): world
+    // coordinates unchanged, or screen coordinates for a sprite that
+    // ignores the draw offset.
+    pub fn sprite_position(&self, x: f32, y: f32, ignores_draw_offset: bool) -> (f32, f32) {
+        if ignores_draw_offset {
+            self.world_to_screen(x, y)
+        } else {
+            (x, y)
+        }
+    }
+
+    pub fn is_visible(&self, rect: PDRect) -> bool {
+        rect.x < self.x + self.width && rect.x + rect.width > self.x
+            && rect.y < self.y + self.height && rect.y + rect.height > self.y
+    }
+}
+
+fn clamp_axis(pos: f32, start: f32, size: f32, view: f32) -> f32 {
+    if size <= view {
+        start + (size - view) / 2.0
+    } else {
+        pos.max(start).min(start + size - view)
+    }
+}
+
+fn round(x: f32) -> i32 {
+    math::floor(x + 0.5) as i32
+}
+
+// Smoothed value noise in [-1, 1]: random values at whole `t`, eased
+// between.
+fn noise(seed: u32, t: f32) -> f32 {
+    let i = math::floor(t);
+    let f = t - i;
+    let (a, b) = (hash(seed, i as i32), hash(seed, i as i32 + 1));
+    a + (b - a) * f * f * (3.0 - 2.0 * f)
+}
+
+fn hash(seed: u32, i: i32) -> f32 {
+    let mut h = seed ^ (i as u32).wrapping_mul(0x27D4_EB2D);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297A_2D39);
+    h ^= h >> 15;
+    (h & 0xFFFF) as f32 / 32767.5 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> PDRect {
+        PDRect { x, y, width, height }
+    }
+
+    #[test]
+    fn bounds_keep_the_view_inside_the_world() {
+        let mut camera = Camera2D::new(400.0, 240.0);
+        camera.set_bounds(Some(rect(0.0, 0.0, 1000.0, 500.0)));
+        camera.set_position(-50.0, 300.0);
+        assert_eq!(camera.position(), (0.0, 260.0));
+        camera.set_position(700.0, 10.0);
+        assert_eq!(camera.position(), (600.0, 10.0));
+        camera.center_on(500.0, 250.0);
+        assert_eq!(camera.position(), (300.0, 130.0));
+    }
+
+    #[test]
+    fn small_worlds_are_centered() {
+        let mut camera = Camera2D::new(400.0, 240.0);
+        camera.set_bounds(Some(rect(10.0, 20.0, 200.0, 100.0)));
+        assert_eq!(camera.position(), (-90.0, -50.0));
+        camera.set_position(500.0, -500.0);
+        assert_eq!(camera.position(), (-90.0, -50.0));
+        // Wide but short: only x is clamped to the edges.
+        camera.set_bounds(Some(rect(0.0, 0.0, 800.0, 100.0)));
+        camera.set_position(500.0, 0.0);
+        assert_eq!(camera.position(), (400.0, -70.0));
+    }
+
+    #[test]
+    fn follow_waits_for_the_target_to_leave_the_deadzone() {
+        let mut camera = Camera2D::new(400.0, 240.0);
+        camera.set_deadzone(rect(150.0, 80.0, 100.0, 80.0));
+        camera.follow(200.0, 120.0);
+        assert_eq!(camera.position(), (0.0, 0.0));
+        camera.follow(300.0, 120.0);
+        assert_eq!(camera.position(), (50.0, 0.0));
+        camera.follow(30.0, 0.0);
+        assert_eq!(camera.position(), (-120.0, -80.0));
+    }
+
+    #[test]
+    fn follow_lerps_toward_the_goal() {
+        let mut camera = Camera2D::new(400.0, 240.0);
+        camera.set_lerp(0.5);
+        camera.follow(300.0, 120.0);
+        assert_eq!(camera.position(), (50.0, 0.0));
+        camera.follow(300.0, 120.0);
+        assert_eq!(camera.position(), (75.0, 0.0));
+        camera.set_lerp(3.0);
+        camera.follow(300.0, 120.0);
+        assert_eq!(camera.position(), (100.0, 0.0));
+    }
+
+    #[test]
+    fn world_and_screen_round_trip() {
+        let mut camera = Camera2D::new(400.0, 240.0);
+        camera.set_position(123.4, -56.6);
+        assert_eq!(camera.offset(), (-123, 57));
+        assert_eq!(camera.world_to_screen(200.0, 100.0), (77.0, 157.0));
+        assert_eq!(camera.screen_to_world(77.0, 157.0), (200.0, 100.0));
+        assert_eq!(camera.sprite_position(200.0, 100.0, true), (77.0, 157.0));
+        assert_eq!(camera.sprite_position(200.0, 100.0, false), (200.0, 100.0));
+        assert!(camera.is_visible(rect(500.0, 0.0, 30.0, 30.0)));
+        assert!(!camera.is_visible(rect(530.0, 0.0, 30.0, 30.0)));
+    }
+
+    #[test]
+    fn shake_decays_to_nothing() {
+        let mut camera = Camera2D::new(400.0, 240.0);
+        camera.set_position(10.0, 20.0);
+        camera.shake(0.6);
+        camera.shake(0.6);
+        assert_eq!(camera.trauma, 1.0);
+        camera.update(200);
+        assert!((camera.trauma - 0.7).abs() < 1e-6);
+        let limit = 0.7 * 0.7 * 8.0;
+        assert!(camera.shake.0.abs() <= limit && camera.shake.1.abs() <= limit);
+        assert_ne!(camera.shake, (0.0, 0.0));
+        camera.update(1000);
+        assert_eq!((camera.trauma, camera.shake), (0.0, (0.0, 0.0)));
+        assert_eq!(camera.offset(), (-10, -20));
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        for i in 0..200 {
+            let value = noise(0x9E37_79B9, i as f32 * 0.37);
+            assert!((-1.0..=1.0).contains(&value), "{}", value);
+        }
+        assert_eq!(noise(7, 3.0), hash(7, 3));
+    }
+}
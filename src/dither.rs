@@ -0,0 +1,201 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use anyhow::Result;
+use crate::bitplane::BitPlane;
+use crate::graphics::{Bitmap, Color, Graphics, SolidColor, BAYER8, COLUMNS, ROWS, ROWSIZE};
+
+// Converts 8-bit grayscale (0 black, 255 white) to 1-bit pixels. The error
+// diffusion algorithms scan left to right, top to bottom; the ordered ones
+// threshold each pixel against a tiled matrix.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    FloydSteinberg,
+    Atkinson,
+    Bayer,
+    BlueNoise,
+}
+
+// (dx, dy, weight) over a divisor, as in the original papers. Atkinson
+// only passes on 6/8 of the error, which keeps highlights and shadows clean.
+const FLOYD_STEINBERG: &[(isize, usize, i16)] = &[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)];
+const ATKINSON: &[(isize, usize, i16)] =
+    &[(1, 0, 1), (2, 0, 1), (-1, 1, 1), (0, 1, 1), (1, 1, 1), (0, 2, 1)];
+
+// A 16x16 void-and-cluster rank matrix: tiles without visible structure.
+const BLUE_NOISE: [[u8; 16]; 16] = [
+    [234, 50, 188, 19, 58, 171, 121, 47, 163, 0, 247, 104, 22, 132, 14, 65],
+    [209, 8, 118, 97, 240, 205, 23, 228, 138, 64, 123, 170, 72, 224, 99, 149],
+    [85, 139, 229, 165, 78, 146, 111, 84, 176, 216, 30, 231, 153, 201, 42, 180],
+    [25, 62, 195, 29, 43, 185, 7, 249, 41, 100, 191, 48, 87, 5, 128, 243],
+    [221, 152, 101, 253, 130, 220, 59, 200, 156, 12, 136, 112, 255, 174, 69, 109],
+    [46, 189, 1, 73, 172, 90, 142, 116, 80, 237, 210, 61, 147, 33, 206, 160],
+    [81, 124, 217, 113, 208, 15, 241, 27, 168, 45, 178, 20, 193, 96, 225, 18],
+    [242, 164, 60, 35, 157, 53, 181, 68, 223, 105, 125, 83, 236, 131, 55, 141],
+    [197, 10, 227, 134, 246, 95, 126, 198, 148, 3, 244, 161, 71, 9, 182, 106],
+    [40, 93, 179, 75, 192, 6, 218, 36, 91, 57, 202, 34, 215, 155, 233, 74],
+    [252, 120, 150, 24, 110, 63, 166, 119, 232, 183, 133, 103, 49, 117, 31, 167],
+    [16, 212, 51, 238, 207, 137, 254, 21, 76, 151, 13, 250, 190, 88, 203, 135],
+    [102, 184, 82, 169, 38, 89, 187, 52, 204, 98, 173, 67, 129, 4, 222, 56],
+    [230, 144, 2, 127, 226, 11, 154, 114, 239, 39, 219, 28, 235, 145, 175, 77],
+    [196, 37, 248, 70, 107, 199, 66, 177, 17, 143, 115, 159, 86, 44, 108, 26],
+    [122, 92, 158, 214, 140, 32, 245, 94, 213, 79, 194, 54, 211, 186, 251, 162],
+];
+
+pub fn dither<B: AsRef<[u8]> + AsMut<[u8]>>(gray: &[u8], width: usize, height: usize,
+                                             algorithm: Algorithm, out: &mut BitPlane<B>) {
+    assert!(gray.len() >= width * height, "grayscale buffer too small");
+    match algorithm {
+        Algorithm::FloydSteinberg => diffuse(gray, width, height, FLOYD_STEINBERG, 16, out),
+        Algorithm::Atkinson => diffuse(gray, width, height, ATKINSON, 8, out),
+        Algorithm::Bayer => ordered(gray, width, height, 64, |x, y| BAYER8[y % 8][x % 8], out),
+        Algorithm::BlueNoise => {
+            ordered(gray, width, height, 256, |x, y| BLUE_NOISE[y % 16][x % 16], out)
+        }
+    }
+}
+
+pub fn to_bitmap(gray: &[u8], width: usize, height: usize, algorithm: Algorithm) -> Result<Bitmap> {
+    let mut bitmap = Bitmap::new(width as u32, height as u32,
+                                 Color::SolidColor(SolidColor::kColorBlack))?;
+    dither(gray, width, height, algorithm, &mut bitmap.data_mut().data);
+    Ok(bitmap)
+}
+
+// Dithers straight into the frame buffer with the top-left corner at
+// (x, y), clipped to the screen, and marks the touched rows updated.
+pub fn to_frame(graphics: &Graphics, gray: &[u8], width: usize, height: usize,
+                x: i32, y: i32, algorithm: Algorithm) -> Result<()> {
+    let rowbytes = (width + 7) / 8;
+    let mut plane = BitPlane::new(vec![0u8; rowbytes * height], width, height, rowbytes);
+    dither(gray, width, height, algorithm, &mut plane);
+    let mut frame = BitPlane::new(graphics.get_frame()?, COLUMNS as usize, ROWS as usize,
+                                  ROWSIZE as usize);
+    frame.blit(&plane, x, y);
+    let first = y.max(0);
+    let last = (y + height as i32 - 1).min(ROWS as i32 - 1);
+    if first <= last {
+        graphics.mark_updated_rows(first..=last);
+    }
+    Ok(())
+}
+
+// A pixel is white when its level is above the midpoint of its rank's
+// slot, so 0 stays all black and 255 all white for any matrix size.
+fn ordered<B, F>(gray: &[u8], width: usize, height: usize, cells: u32, rank: F,
+                 out: &mut BitPlane<B>)
+where
+    B: AsRef<[u8]> + AsMut<[u8]>,
+    F: Fn(usize, usize) -> u8,
+{
+    for y in 0..height {
+        for x in 0..width {
+            let level = gray[y * width + x] as u32;
+            let white = level * cells > rank(x, y) as u32 * 255 + 127;
+            out.set(x as i32, y as i32, white);
+        }
+    }
+}
+
+fn diffuse<B: AsRef<[u8]> + AsMut<[u8]>>(gray: &[u8], width: usize, height: usize,
+                                         kernel: &[(isize, usize, i16)], divisor: i16,
+                                         out: &mut BitPlane<B>) {
+    // Three rows of accumulated error, padded by two columns on each side
+    // so the kernel never needs bounds checks.
+    let stride = width + 4;
+    let mut errors: Vec<i16> = vec![0; stride * 3];
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y % 3) * stride + x + 2;
+            let value = gray[y * width + x] as i16 + errors[index] / divisor;
+            let white = value >= 128;
+            out.set(x as i32, y as i32, white);
+            let error = value - if white { 255 } else { 0 };
+            for &(dx, dy, weight) in kernel {
+                let row = (y + dy) % 3;
+                errors[row * stride + (x as isize + 2 + dx) as usize] += error * weight;
+            }
+        }
+        let row = y % 3;
+        for e in &mut errors[row * stride..(row + 1) * stride] {
+            *e = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    const WIDTH: usize = 16;
+    const HEIGHT: usize = 4;
+
+    // A left-to-right ramp from 8 to 248, the same on every row.
+    fn gradient() -> Vec<u8> {
+        (0..WIDTH * HEIGHT).map(|i| (i % WIDTH * 16 + 8) as u8).collect()
+    }
+
+    // One string per row, `#` for white.
+    fn render(gray: &[u8], algorithm: Algorithm) -> Vec<String> {
+        let rowbytes = (WIDTH + 7) / 8;
+        let mut plane = BitPlane::new(vec![0u8; rowbytes * HEIGHT], WIDTH, HEIGHT, rowbytes);
+        dither(gray, WIDTH, HEIGHT, algorithm, &mut plane);
+        (0..HEIGHT as i32).map(|y| {
+            (0..WIDTH as i32).map(|x| if plane.get(x, y) { '#' } else { '.' }).collect()
+        }).collect()
+    }
+
+    // Golden output: a change to a kernel, a matrix or the thresholds
+    // shows up here.
+    #[test]
+    fn floyd_steinberg_gradient() {
+        assert_eq!(render(&gradient(), Algorithm::FloydSteinberg), [
+            ".....#.#.#######",
+            "...#..#.#.#.####",
+            ".....#..#.##.###",
+            "...#..#.##.#####",
+        ]);
+    }
+
+    #[test]
+    fn atkinson_gradient() {
+        assert_eq!(render(&gradient(), Algorithm::Atkinson), [
+            ".......##.######",
+            ".....#..########",
+            ".....##..#.#####",
+            "....#..##.##.###",
+        ]);
+    }
+
+    #[test]
+    fn bayer_gradient() {
+        assert_eq!(render(&gradient(), Algorithm::Bayer), [
+            "#.#.#.#.########",
+            ".....#.#.#.#.#.#",
+            "..#.#.#.#.######",
+            ".......#.#.#.###",
+        ]);
+    }
+
+    #[test]
+    fn blue_noise_gradient() {
+        assert_eq!(render(&gradient(), Algorithm::BlueNoise), [
+            "...##..#.#.#####",
+            ".#....#..####.##",
+            ".......#..#.####",
+            "...##.#.##.#####",
+        ]);
+    }
+
+    #[test]
+    fn black_and_white_stay_solid() {
+        for &algorithm in &[Algorithm::FloydSteinberg, Algorithm::Atkinson,
+                            Algorithm::Bayer, Algorithm::BlueNoise] {
+            for &(level, pixel) in &[(0, '.'), (255, '#')] {
+                let row: String = core::iter::repeat(pixel).take(WIDTH).collect();
+                let rows = render(&[level; WIDTH * HEIGHT], algorithm);
+                assert!(rows.iter().all(|r| *r == row), "{:?} at {}", algorithm, level);
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use core::convert::Infallible;
+use anyhow::Result;
+use embedded_graphics_core::Pixel;
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{Dimensions, OriginDimensions, Size};
+use embedded_graphics_core::pixelcolor::BinaryColor;
+use embedded_graphics_core::primitives::Rectangle;
+use crate::bitplane::BitPlane;
+use crate::graphics::{Graphics, COLUMNS, ROWS, ROWSIZE};
+
+// `BinaryColor::On` is ink: it draws black, and `Off` draws white.
+impl<B: AsRef<[u8]> + AsMut<[u8]>> OriginDimensions for BitPlane<B> {
+    fn size(&self) -> Size {
+        Size::new(self.width() as u32, self.height() as u32)
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> DrawTarget for BitPlane<B> {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            self.set(point.x, point.y, color.is_off());
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.fill(color.is_off());
+        Ok(())
+    }
+}
+
+// The frame buffer as an embedded-graphics target. Rows touched by each
+// draw call are passed to `mark_updated_rows` so the SDK refreshes them.
+pub struct FrameBuffer {
+    graphics: Graphics,
+    plane: BitPlane<&'static mut [u8]>,
+}
+
+impl FrameBuffer {
+    pub fn new(graphics: Graphics) -> Result<Self> {
+        let frame = graphics.get_frame()?;
+        let plane = BitPlane::new(frame, COLUMNS as usize, ROWS as usize, ROWSIZE as usize);
+        Ok(Self { graphics, plane })
+    }
+
+    pub fn plane(&mut self) -> &mut BitPlane<&'static mut [u8]> {
+        &mut self.plane
+    }
+}
+
+impl OriginDimensions for FrameBuffer {
+    fn size(&self) -> Size {
+        self.plane.size()
+    }
+}
+
+impl DrawTarget for FrameBuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let (mut first, mut last) = (i32::MAX, i32::MIN);
+        for Pixel(point, color) in pixels {
+            if self.plane.contains(point.x, point.y) {
+                self.plane.set(point.x, point.y, color.is_off());
+                first = first.min(point.y);
+                last = last.max(point.y);
+            }
+        }
+        if first <= last {
+            self.graphics.mark_updated_rows(first..=last);
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        if let Some(bottom_right) = area.bottom_right() {
+            for y in area.top_left.y..=bottom_right.y {
+                for x in area.top_left.x..=bottom_right.x {
+                    self.plane.set(x, y, color.is_off());
+                }
+            }
+            self.graphics.mark_updated_rows(area.top_left.y..=bottom_right.y);
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.plane.fill(color.is_off());
+        self.graphics.mark_updated_rows(0..=ROWS as i32 - 1);
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@ use sys;
 use sys::cty;
 use crate::Playdate;
 use anyhow::{Result, Error, ensure};
-use alloc::format;
+use alloc::vec::Vec;
 use cstr_core::{CString, CStr};
 
 pub use sys::FileOptions;
@@ -35,19 +35,28 @@ impl Filesystem {
 
     pub fn stat(&self, path: &str) -> Result<FileStat> {
         let mut file_stat = FileStat::default();
-        pd_call!(self.0, stat, str_to_ptr(path), &mut file_stat => file_stat)
+        let c_path = CString::new(path).map_err(Error::msg)?;
+        pd_call!(self.0, stat, c_path.as_ptr(), &mut file_stat => file_stat)
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.stat(path).is_ok()
     }
 
     pub fn mkdir(&self, path: &str) -> Result<()> {
-        pd_call!(self.0, mkdir, str_to_ptr(path) => ())
+        let c_path = CString::new(path).map_err(Error::msg)?;
+        pd_call!(self.0, mkdir, c_path.as_ptr() => ())
     }
 
     pub fn unlink(&self, name: &str, recursive: bool) -> Result<()> {
-        pd_call!(self.0, unlink, str_to_ptr(name), recursive as i32 => ())
+        let c_name = CString::new(name).map_err(Error::msg)?;
+        pd_call!(self.0, unlink, c_name.as_ptr(), recursive as i32 => ())
     }
-    
+
     pub fn rename(&self, from: &str, to: &str) -> Result<()> {
-        pd_call!(self.0, rename, str_to_ptr(from), str_to_ptr(to) => ())
+        let c_from = CString::new(from).map_err(Error::msg)?;
+        let c_to = CString::new(to).map_err(Error::msg)?;
+        pd_call!(self.0, rename, c_from.as_ptr(), c_to.as_ptr() => ())
     }
 
     pub fn get_err(&self) -> Result<&str> {
@@ -66,10 +75,32 @@ impl File {
         fs_call!(read, self.0, buf.as_mut_ptr() as *mut cty::c_void, buf.len() as u32)
     }
 
+    pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
+        let mut chunk = [0u8; 256];
+        loop {
+            let len = self.read(&mut chunk)? as usize;
+            if len == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..len]);
+        }
+        Ok(buf.len() - start)
+    }
+
     pub fn write(&mut self, buf: &[u8]) -> Result<i32> {
         fs_call!(write, self.0, buf.as_ptr() as *const cty::c_void, buf.len() as u32)
     }
 
+    pub fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            let len = self.write(buf)? as usize;
+            ensure!(len > 0, "Error write returned 0 bytes");
+            buf = &buf[len..];
+        }
+        Ok(())
+    }
+
     pub fn flush(&mut self) -> Result<i32> {
         fs_call!(flush, self.0)
     }
@@ -83,6 +114,12 @@ impl File {
     }
 }
 
+impl core::fmt::Write for File {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
 impl Drop for File {
     fn drop(&mut self) { pd_call!(Playdate::get_filesystem().0, close, self.0); }
 }
@@ -94,7 +131,3 @@ pub enum Whence {
     Cur = sys::SEEK_CUR as i32,
     End = sys::SEEK_END as i32,
 }
-
-fn str_to_ptr(string: &str) -> *const cty::c_char {
-    CString::new(string).map_err(Error::msg).unwrap().as_ptr()
-}
\ No newline at end of file
@@ -1,9 +1,13 @@
 use anyhow::{Result, anyhow, ensure};
-use core::{ptr, ops::RangeInclusive, slice};
+use core::{ptr, ops::{Deref, RangeInclusive}, slice};
+use core::marker::PhantomData;
+use alloc::vec::Vec;
 use cstr_core::{CString, CStr};
 use sys;
 
 use crate::Playdate;
+use crate::math;
+pub use crate::bitplane::BitPlane;
 
 pub use sys::LCD_COLUMNS as COLUMNS;
 pub use sys::LCD_ROWS as ROWS;
@@ -16,8 +20,26 @@ pub use sys::LCDLineCapStyle as LineCapStyle;
 pub use sys::LCDFontLanguage as FontLanguage;
 pub use sys::PDStringEncoding as StringEncoding;
 pub use sys::LCDRect as Rect;
+pub use sys::LCDPolygonFillRule as PolygonFillRule;
 pub use sys::LCDSprite as Sprite;
 
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+}
+
+// Like the SDK's LCDMakeRect: `right` and `bottom` are exclusive.
+pub const fn make_rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+    Rect { left: x, right: x + width, top: y, bottom: y + height }
+}
+
 #[derive(Copy, Clone)]
 pub struct Graphics(*const sys::playdate_graphics);
 
@@ -57,19 +79,19 @@ impl Graphics {
         }
     }
 
-    pub fn get_debug_bitmap(&self) -> Result<Bitmap> {
+    pub fn get_debug_bitmap(&self) -> Result<BitmapRef<'static>> {
         unsafe {
             let ptr = (*self.0).getDebugBitmap.unwrap()();
             ensure!(!ptr.is_null(), {"Null pointer from get_debug_bitmap"});
-            Ok(Bitmap { bitmap: ptr })
+            Ok(BitmapRef::from_raw(ptr))
         }
     }
 
-    pub fn get_frame_buffer_bitmap(&self) -> Result<Bitmap> {
+    pub fn get_frame_buffer_bitmap(&self) -> Result<BitmapRef<'static>> {
         unsafe {
             let ptr = (*self.0).getFrameBufferBitmap.unwrap()();
             ensure!(!ptr.is_null(), {"Null pointer from get_frame_buffer_bitmap"});
-            Ok(Bitmap { bitmap: ptr })
+            Ok(BitmapRef::from_raw(ptr))
         }
     }
 
@@ -87,46 +109,110 @@ impl Graphics {
 
     pub fn set_draw_offset(&self, dx: i32, dy: i32) {
         unsafe {
+            STATE.offset = (dx, dy);
             (*self.0).setDrawOffset.unwrap()(dx, dy)
         }
     }
 
-    pub fn push_context(&self, target: Bitmap) {
+    pub fn push_draw_offset(&self, dx: i32, dy: i32) -> DrawOffsetGuard {
+        let previous = unsafe { STATE.offset };
+        self.set_draw_offset(dx, dy);
+        DrawOffsetGuard { graphics: *self, previous }
+    }
+
+    // Draws into `target` until the returned guard is dropped.
+    pub fn push_context<'a, T: AsBitmapRef + ?Sized>(&self, target: &'a mut T) -> DrawTarget<'a> {
+        self.push_context_raw(target.as_bitmap_ref().bitmap)
+    }
+
+    pub fn push_screen_context(&self) -> DrawTarget<'static> {
+        self.push_context_raw(ptr::null_mut())
+    }
+
+    fn push_context_raw<'a>(&self, target: *mut sys::LCDBitmap) -> DrawTarget<'a> {
         unsafe {
-            (*self.0).pushContext.unwrap()(target.bitmap)
+            CONTEXTS.push(STATE);
+            STATE = DEFAULT_STATE;
+            (*self.0).pushContext.unwrap()(target);
         }
+        DrawTarget { graphics: *self, _marker: PhantomData }
     }
 
-     pub fn pop_context(&self) {
+    fn pop_context(&self) {
         unsafe {
-            (*self.0).popContext.unwrap()()
+            (*self.0).popContext.unwrap()();
+            STATE = CONTEXTS.pop().unwrap_or(DEFAULT_STATE);
         }
     }
 
-    pub fn set_stencil(&self, stencil: Bitmap) {
+    // The SDK keeps the pointer: `stencil` must stay alive until it is
+    // cleared or replaced. `push_stencil` ties that to a guard instead.
+    pub unsafe fn set_stencil(&self, stencil: &BitmapRef) {
+        self.set_stencil_raw(stencil.bitmap)
+    }
+
+    pub fn clear_stencil(&self) {
+        self.set_stencil_raw(ptr::null_mut())
+    }
+
+    fn set_stencil_raw(&self, stencil: *mut sys::LCDBitmap) {
         unsafe {
-            (*self.0).setStencil.unwrap()(stencil.bitmap)
+            STATE.stencil = stencil;
+            (*self.0).setStencil.unwrap()(stencil)
         }
     }
 
+    pub fn push_stencil<'a>(&self, stencil: &'a BitmapRef) -> StencilGuard<'a> {
+        let previous = unsafe { STATE.stencil };
+        self.set_stencil_raw(stencil.bitmap);
+        StencilGuard { graphics: *self, previous, _marker: PhantomData }
+    }
+
     pub fn set_draw_mode(&self, mode: BitmapDrawMode) {
         unsafe {
+            STATE.mode = mode;
             (*self.0).setDrawMode.unwrap()(mode)
         }
     }
 
+    pub fn push_draw_mode(&self, mode: BitmapDrawMode) -> DrawModeGuard {
+        let previous = unsafe { STATE.mode };
+        self.set_draw_mode(mode);
+        DrawModeGuard { graphics: *self, previous }
+    }
+
     pub fn set_clip_rect(&self, x: i32, y: i32, width: i32, height: i32) {
         unsafe {
+            STATE.clip = Some((x, y, width, height));
             (*self.0).setClipRect.unwrap()(x, y, width, height)
         }
     }
 
     pub fn clear_clip_rect(&self) {
         unsafe {
+            STATE.clip = None;
             (*self.0).clearClipRect.unwrap()()
         }
     }
 
+    // Narrows the active clip rather than replacing it, so nested clips nest.
+    pub fn push_clip_rect(&self, x: i32, y: i32, width: i32, height: i32) -> ClipRectGuard {
+        let previous = unsafe { STATE.clip };
+        let (x, y, width, height) = match previous {
+            Some(clip) => intersect(clip, (x, y, width, height)),
+            None => (x, y, width, height),
+        };
+        self.set_clip_rect(x, y, width, height);
+        ClipRectGuard { graphics: *self, previous }
+    }
+
+    fn restore_clip_rect(&self, clip: Option<(i32, i32, i32, i32)>) {
+        match clip {
+            Some((x, y, width, height)) => self.set_clip_rect(x, y, width, height),
+            None => self.clear_clip_rect(),
+        }
+    }
+
     pub fn set_line_cap_style(&self, end_cap_style: LineCapStyle) {
         unsafe {
             (*self.0).setLineCapSyle.unwrap()(end_cap_style)
@@ -187,32 +273,350 @@ impl Graphics {
         }
     }
 
+    pub fn fill_polygon(&self, points: &[Point], rule: PolygonFillRule, color: Color) {
+        let mut coords = Vec::with_capacity(points.len() * 2);
+        for p in points {
+            coords.push(p.x);
+            coords.push(p.y);
+        }
+        unsafe {
+            (*self.0).fillPolygon.unwrap()(
+                points.len() as i32, coords.as_mut_ptr(), color.into(), rule)
+        }
+    }
+
+    pub fn draw_polyline(&self, points: &[Point], width: i32, color: Color) {
+        for pair in points.windows(2) {
+            self.draw_line(pair[0].x, pair[0].y, pair[1].x, pair[1].y, width, color);
+        }
+    }
+
+    pub fn draw_polygon(&self, points: &[Point], width: i32, color: Color) {
+        self.draw_polyline(points, width, color);
+        if let (Some(first), Some(last)) = (points.first(), points.last()) {
+            self.draw_line(last.x, last.y, first.x, first.y, width, color);
+        }
+    }
+
+    // Drawn as a filled quad plus caps, so the result doesn't depend on the
+    // global cap style set with set_line_cap_style.
+    pub fn draw_thick_line(&self, from: Point, to: Point, width: i32,
+                           cap: LineCapStyle, color: Color) {
+        let (dx, dy) = ((to.x - from.x) as f32, (to.y - from.y) as f32);
+        let len = math::sqrt(dx * dx + dy * dy);
+        if len == 0.0 {
+            if cap != LineCapStyle::kLineCapStyleButt {
+                self.fill_rect(from.x - width / 2, from.y - width / 2, width, width, color);
+            }
+            return;
+        }
+        let half = width as f32 / 2.0;
+        let (ux, uy) = (dx / len * half, dy / len * half);
+        let (ex, ey) = if cap == LineCapStyle::kLineCapStyleSquare { (ux, uy) } else { (0.0, 0.0) };
+        let corner = |p: Point, sx: f32, sy: f32| {
+            Point::new((p.x as f32 + sx + 0.5) as i32, (p.y as f32 + sy + 0.5) as i32)
+        };
+        let quad = [
+            corner(from, -uy - ex, ux - ey),
+            corner(to, -uy + ex, ux + ey),
+            corner(to, uy + ex, -ux + ey),
+            corner(from, uy - ex, -ux - ey),
+        ];
+        self.fill_polygon(&quad, PolygonFillRule::kPolygonFillNonZero, color);
+        if cap == LineCapStyle::kLineCapStyleRound {
+            for p in [from, to].iter() {
+                self.fill_ellipse(p.x - width / 2, p.y - width / 2, width, width, 0.0, 360.0, color);
+            }
+        }
+    }
+
+    // Angles are in degrees, clockwise from 12 o'clock, as in draw_ellipse.
+    pub fn draw_arc(&self, center: Point, radius: i32, start_angle: f32, end_angle: f32,
+                    line_width: i32, color: Color) {
+        self.draw_ellipse(center.x - radius, center.y - radius, 2 * radius, 2 * radius,
+                          line_width, start_angle, end_angle, color);
+    }
+
+    pub fn fill_arc(&self, center: Point, radius: i32, start_angle: f32, end_angle: f32,
+                    color: Color) {
+        self.fill_ellipse(center.x - radius, center.y - radius, 2 * radius, 2 * radius,
+                          start_angle, end_angle, color);
+    }
+
+    pub fn draw_round_rect(&self, rect: Rect, radius: i32, line_width: i32, color: Color) {
+        let (x, y) = (rect.left, rect.top);
+        let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+        let r = radius.min(width / 2).min(height / 2).max(0);
+        let d = 2 * r;
+        self.fill_rect(x + r, y, width - d, line_width, color);
+        self.fill_rect(x + r, y + height - line_width, width - d, line_width, color);
+        self.fill_rect(x, y + r, line_width, height - d, color);
+        self.fill_rect(x + width - line_width, y + r, line_width, height - d, color);
+        if r > 0 {
+            self.draw_ellipse(x, y, d, d, line_width, 270.0, 360.0, color);
+            self.draw_ellipse(x + width - d, y, d, d, line_width, 0.0, 90.0, color);
+            self.draw_ellipse(x + width - d, y + height - d, d, d, line_width, 90.0, 180.0, color);
+            self.draw_ellipse(x, y + height - d, d, d, line_width, 180.0, 270.0, color);
+        }
+    }
+
+    pub fn fill_round_rect(&self, rect: Rect, radius: i32, color: Color) {
+        let (x, y) = (rect.left, rect.top);
+        let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+        let r = radius.min(width / 2).min(height / 2).max(0);
+        let d = 2 * r;
+        self.fill_rect(x + r, y, width - d, height, color);
+        self.fill_rect(x, y + r, r, height - d, color);
+        self.fill_rect(x + width - r, y + r, r, height - d, color);
+        if r > 0 {
+            self.fill_ellipse(x, y, d, d, 270.0, 360.0, color);
+            self.fill_ellipse(x + width - d, y, d, d, 0.0, 90.0, color);
+            self.fill_ellipse(x + width - d, y + height - d, d, d, 90.0, 180.0, color);
+            self.fill_ellipse(x, y + height - d, d, d, 180.0, 270.0, color);
+        }
+    }
+
     pub fn draw_text(&self,text: &str, encoding: sys::PDStringEncoding,
                      x: i32, y: i32) -> i32 {
-        let len = text.len() as sys::cty::c_ulong;
-        let c_str = CString::new(text).unwrap();
+        let (buf, len) = encode_text(text, encoding);
         unsafe {
             (*self.0).drawText.unwrap()(
-                c_str.as_ptr() as *const sys::cty::c_void,
-                len.into(), encoding, x, y)
+                buf.as_ptr() as *const sys::cty::c_void,
+                len, encoding, x, y)
+        }
+    }
+}
+
+// Converts `text` for the SDK's text calls, returning the buffer and its
+// length in code units of `encoding`. 16-bit text is little-endian.
+fn encode_text(text: &str, encoding: StringEncoding) -> (Vec<u8>, sys::size_t) {
+    match encoding {
+        StringEncoding::k16BitLEEncoding => {
+            let mut buf = Vec::with_capacity(text.len() * 2 + 2);
+            let mut len = 0;
+            for unit in text.encode_utf16() {
+                buf.extend_from_slice(&unit.to_le_bytes());
+                len += 1;
+            }
+            buf.extend_from_slice(&[0, 0]);
+            (buf, len)
         }
+        _ => {
+            let mut buf = Vec::with_capacity(text.len() + 1);
+            buf.extend_from_slice(text.as_bytes());
+            buf.push(0);
+            (buf, text.len() as sys::size_t)
+        }
+    }
+}
+
+// The SDK has no getters for the drawing state, so the values last set
+// through `Graphics` are tracked here for the scoped guards to restore.
+// `push_context` starts a fresh context, so the state is stacked with it.
+#[derive(Copy, Clone)]
+struct DrawState {
+    clip: Option<(i32, i32, i32, i32)>,
+    mode: BitmapDrawMode,
+    offset: (i32, i32),
+    stencil: *mut sys::LCDBitmap,
+}
+
+const DEFAULT_STATE: DrawState = DrawState {
+    clip: None,
+    mode: BitmapDrawMode::kDrawModeCopy,
+    offset: (0, 0),
+    stencil: ptr::null_mut(),
+};
+
+static mut STATE: DrawState = DEFAULT_STATE;
+static mut CONTEXTS: Vec<DrawState> = Vec::new();
+
+#[must_use]
+pub struct DrawTarget<'a> {
+    graphics: Graphics,
+    _marker: PhantomData<&'a mut ()>,
+}
+
+impl Drop for DrawTarget<'_> {
+    fn drop(&mut self) {
+        self.graphics.pop_context();
+    }
+}
+
+// Overlap of two (x, y, width, height) rects; empty when they don't meet.
+fn intersect(a: (i32, i32, i32, i32), b: (i32, i32, i32, i32)) -> (i32, i32, i32, i32) {
+    let (left, top) = (a.0.max(b.0), a.1.max(b.1));
+    let right = (a.0 + a.2).min(b.0 + b.2);
+    let bottom = (a.1 + a.3).min(b.1 + b.3);
+    (left, top, (right - left).max(0), (bottom - top).max(0))
+}
+
+#[must_use]
+pub struct ClipRectGuard {
+    graphics: Graphics,
+    previous: Option<(i32, i32, i32, i32)>,
+}
+
+impl Drop for ClipRectGuard {
+    fn drop(&mut self) {
+        self.graphics.restore_clip_rect(self.previous);
+    }
+}
+
+#[must_use]
+pub struct DrawModeGuard {
+    graphics: Graphics,
+    previous: BitmapDrawMode,
+}
+
+impl Drop for DrawModeGuard {
+    fn drop(&mut self) {
+        self.graphics.set_draw_mode(self.previous);
+    }
+}
+
+#[must_use]
+pub struct StencilGuard<'a> {
+    graphics: Graphics,
+    previous: *mut sys::LCDBitmap,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl Drop for StencilGuard<'_> {
+    fn drop(&mut self) {
+        self.graphics.set_stencil_raw(self.previous);
     }
 }
 
-pub enum Color {
+#[must_use]
+pub struct DrawOffsetGuard {
+    graphics: Graphics,
+    previous: (i32, i32),
+}
+
+impl Drop for DrawOffsetGuard {
+    fn drop(&mut self) {
+        let (dx, dy) = self.previous;
+        self.graphics.set_draw_offset(dx, dy);
+    }
+}
+
+// Patterns are passed to the SDK by address, so a patterned color borrows
+// its `Pattern` for as long as the color is in use.
+#[derive(Copy, Clone)]
+pub enum Color<'a> {
     SolidColor(SolidColor),
-    Pattern(sys::LCDPattern),
+    Pattern(&'a Pattern),
 }
 
-impl From<Color> for usize {
+impl From<Color<'_>> for usize {
     fn from(color: Color) -> Self {
         match color {
             Color::SolidColor(solid_color) => solid_color as usize,
-            Color::Pattern(pattern) => {
-                let pattern_ptr = &pattern as *const u8;
-                pattern_ptr as usize
+            Color::Pattern(pattern) => pattern.0.as_ptr() as usize,
+        }
+    }
+}
+
+// An 8x8 pattern: eight rows of pixels (set bits are white) followed by
+// eight rows of mask (set bits are drawn).
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pattern(pub sys::LCDPattern);
+
+pub(crate) const BAYER8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+// The top-left n x n corner of the 8x8 Bayer matrix, scaled down, is the
+// n x n Bayer matrix, so all three sizes are built from the one table.
+const fn bayer_pattern(n: usize, level: usize) -> Pattern {
+    let scale = 64 / (n * n);
+    let mut bitmap = [0u8; 8];
+    let mut y = 0;
+    while y < 8 {
+        let mut x = 0;
+        while x < 8 {
+            if (BAYER8[y % n][x % n] as usize / scale) < level {
+                bitmap[y] |= 0x80 >> x;
             }
+            x += 1;
+        }
+        y += 1;
+    }
+    Pattern::new(bitmap)
+}
+
+const fn bayer_table<const N: usize>(n: usize) -> [Pattern; N] {
+    let mut table = [Pattern([0; 16]); N];
+    let mut level = 0;
+    while level < N {
+        table[level] = bayer_pattern(n, level);
+        level += 1;
+    }
+    table
+}
+
+static BAYER2_PATTERNS: [Pattern; 5] = bayer_table(2);
+static BAYER4_PATTERNS: [Pattern; 17] = bayer_table(4);
+static BAYER8_PATTERNS: [Pattern; 65] = bayer_table(8);
+
+impl Pattern {
+    pub const fn new(bitmap: [u8; 8]) -> Self {
+        Self::with_mask(bitmap, [0xFF; 8])
+    }
+
+    pub const fn with_mask(bitmap: [u8; 8], mask: [u8; 8]) -> Self {
+        let mut pattern = [0u8; 16];
+        let mut i = 0;
+        while i < 8 {
+            pattern[i] = bitmap[i];
+            pattern[i + 8] = mask[i];
+            i += 1;
         }
+        Pattern(pattern)
+    }
+
+    pub fn bitmap(&self) -> [u8; 8] {
+        let mut bitmap = [0u8; 8];
+        bitmap.copy_from_slice(&self.0[..8]);
+        bitmap
+    }
+
+    pub fn mask(&self) -> [u8; 8] {
+        let mut mask = [0u8; 8];
+        mask.copy_from_slice(&self.0[8..]);
+        mask
+    }
+
+    // Gray levels run from 0 (black) to 64 (white) for every matrix size;
+    // smaller matrices have fewer distinct steps.
+    pub fn bayer2(level: u8) -> &'static Pattern {
+        &BAYER2_PATTERNS[(level.min(64) as usize * 4 + 32) / 64]
+    }
+
+    pub fn bayer4(level: u8) -> &'static Pattern {
+        &BAYER4_PATTERNS[(level.min(64) as usize * 16 + 32) / 64]
+    }
+
+    pub fn bayer8(level: u8) -> &'static Pattern {
+        &BAYER8_PATTERNS[level.min(64) as usize]
+    }
+
+    pub fn gray(level: u8) -> &'static Pattern {
+        Self::bayer8(level)
+    }
+
+    pub fn from_gray(value: f32) -> &'static Pattern {
+        let value = if value > 1.0 { 1.0 } else if value > 0.0 { value } else { 0.0 };
+        Self::gray((value * 64.0 + 0.5) as u8)
     }
 }
 
@@ -226,60 +630,162 @@ impl Font {
         let c_path = CString::new(path).unwrap();
         unsafe {
             let graphics = Playdate::get_graphics().0;
-            let out_err = ptr::null_mut();
-            let font = (*graphics).loadFont.unwrap()(c_path.as_ptr(), out_err);
-            if out_err.is_null() {
+            let mut out_err = ptr::null();
+            let font = (*graphics).loadFont.unwrap()(c_path.as_ptr(), &mut out_err);
+            if !out_err.is_null() {
+                Err(anyhow!(CStr::from_ptr(out_err).to_str().unwrap_or("failed to load font")))
+            } else {
+                ensure!(!font.is_null(), {"failed to load font"});
                 Ok(Font {font})
+            }
+        }
+    }
+
+    pub fn height(&self) -> i32 {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            (*graphics).getFontHeight.unwrap()(self.font) as i32
+        }
+    }
+
+    pub fn text_width(&self, text: &str, tracking: i32) -> i32 {
+        self.text_width_encoded(text, StringEncoding::kUTF8Encoding, tracking)
+    }
+
+    pub fn text_width_encoded(&self, text: &str, encoding: StringEncoding, tracking: i32) -> i32 {
+        let (buf, len) = encode_text(text, encoding);
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            (*graphics).getTextWidth.unwrap()(
+                self.font, buf.as_ptr() as *const sys::cty::c_void, len, encoding, tracking)
+        }
+    }
+
+    pub fn page(&self, c: char) -> Option<FontPage<'_>> {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            let page = (*graphics).getFontPage.unwrap()(self.font, c as u32);
+            if page.is_null() {
+                None
             } else {
-                Err(anyhow!(CStr::from_ptr(*out_err).to_str().unwrap()))
+                Some(FontPage { page, _marker: PhantomData })
             }
         }
     }
+
+    pub fn glyph(&self, c: char) -> Option<Glyph<'_>> {
+        self.page(c)?.glyph(c)
+    }
+
+    // Positions each character of `text` the way draw_text would, applying
+    // kerning and tracking, so glyphs can be drawn one at a time.
+    pub fn glyphs<'a>(&'a self, text: &'a str, tracking: i32) -> Glyphs<'a> {
+        Glyphs { font: self, chars: text.chars().peekable(), x: 0, tracking }
+    }
 }
 
-pub struct Bitmap {
-    pub bitmap: *mut sys::LCDBitmap,
+// The glyphs of a font sharing the same upper bits of their code point.
+// Borrowed from the font; freed with it.
+pub struct FontPage<'a> {
+    page: *mut sys::LCDFontPage,
+    _marker: PhantomData<&'a Font>,
 }
 
-impl Bitmap {
-    pub fn new(width: u32, height: u32, bgcolor: Color) -> Result<Self> {
+impl<'a> FontPage<'a> {
+    pub fn glyph(&self, c: char) -> Option<Glyph<'a>> {
         unsafe {
             let graphics = Playdate::get_graphics().0;
-            let ptr = (*graphics).newBitmap
-                .unwrap()(width as i32, height as i32, bgcolor.into());
-            ensure!(!ptr.is_null(), {"failed to create bitmap"});
-            Ok(Self { bitmap: ptr })
+            let mut bitmap = ptr::null_mut();
+            let mut advance = 0;
+            let glyph = (*graphics).getPageGlyph.unwrap()(
+                self.page, c as u32, &mut bitmap, &mut advance);
+            if glyph.is_null() {
+                return None;
+            }
+            let bitmap = if bitmap.is_null() { None } else { Some(BitmapRef::from_raw(bitmap)) };
+            Some(Glyph { glyph, code: c, bitmap, advance })
         }
     }
+}
 
-    pub fn load(path: &str) -> Result<Self> {
+pub struct Glyph<'a> {
+    glyph: *mut sys::LCDFontGlyph,
+    code: char,
+    bitmap: Option<BitmapRef<'a>>,
+    advance: i32,
+}
+
+impl<'a> Glyph<'a> {
+    pub fn code(&self) -> char {
+        self.code
+    }
+
+    pub fn advance(&self) -> i32 {
+        self.advance
+    }
+
+    pub fn bitmap(&self) -> Option<&BitmapRef<'a>> {
+        self.bitmap.as_ref()
+    }
+
+    pub fn kerning(&self, next: char) -> i32 {
         unsafe {
             let graphics = Playdate::get_graphics().0;
-            let c_path = CString::new(path).unwrap();
-            let outerr = ptr::null_mut();
-            let ptr = (*graphics).loadBitmap.unwrap()(c_path.as_ptr(), outerr);
-            ensure!(!ptr.is_null(), {"failed to load bitmap"});
-            Ok(Self { bitmap: ptr })
+            (*graphics).getGlyphKerning.unwrap()(self.glyph, self.code as u32, next as u32)
         }
     }
+}
 
-    pub fn copy(bitmap: Bitmap) -> Result<Self> {
-        unsafe {
-            let graphics = Playdate::get_graphics().0;
-            let ptr = (*graphics).copyBitmap.unwrap()(bitmap.bitmap);
-            ensure!(!ptr.is_null(), {"failed to copy bitmap"});
-            Ok(Self { bitmap: ptr })
+pub struct Glyphs<'a> {
+    font: &'a Font,
+    chars: core::iter::Peekable<core::str::Chars<'a>>,
+    x: i32,
+    tracking: i32,
+}
+
+impl<'a> Iterator for Glyphs<'a> {
+    // The glyph and its x offset from the start of the text. Characters
+    // missing from the font are skipped.
+    type Item = (i32, Glyph<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let c = self.chars.next()?;
+            let glyph = match self.font.glyph(c) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            let x = self.x;
+            self.x += glyph.advance + self.tracking;
+            if let Some(&next) = self.chars.peek() {
+                self.x += glyph.kerning(next);
+            }
+            return Some((x, glyph));
         }
     }
+}
+
+// A bitmap owned by the SDK or by another object (the frame buffer, a
+// table entry, a sprite's image). It is never freed; `Bitmap` derefs to it
+// so every drawing method is available on both.
+pub struct BitmapRef<'a> {
+    pub bitmap: *mut sys::LCDBitmap,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl<'a> BitmapRef<'a> {
+    pub(crate) fn from_raw(bitmap: *mut sys::LCDBitmap) -> Self {
+        Self { bitmap, _marker: PhantomData }
+    }
 
     pub fn load_into(&mut self, path: &str) -> Result<()> {
          unsafe {
              let graphics = Playdate::get_graphics().0;
              let c_path = CString::new(path).unwrap();
-             let outerr = ptr::null_mut();
+             let mut outerr = ptr::null();
              (*graphics).loadIntoBitmap
-                 .unwrap()(c_path.as_ptr(), self.bitmap, outerr);
-             ensure!(!outerr.is_null(), {"failed to load bitmap"});
+                 .unwrap()(c_path.as_ptr(), self.bitmap, &mut outerr);
+             ensure!(outerr.is_null(), {"failed to load bitmap"});
              Ok(())
         }
     }
@@ -313,14 +819,317 @@ impl Bitmap {
                 .unwrap()(self.bitmap, x, y, xscale, yscale)
         }
     }
+
+    pub fn draw_rotated(&self, x: i32, y: i32, angle: f32, centerx: f32, centery: f32,
+                        xscale: f32, yscale: f32) {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            (*graphics).drawRotatedBitmap
+                .unwrap()(self.bitmap, x, y, angle, centerx, centery, xscale, yscale)
+        }
+    }
+
+    pub fn rotated(&self, angle: f32, xscale: f32, yscale: f32) -> Result<Bitmap> {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            let mut alloced_size = 0;
+            let ptr = (*graphics).rotatedBitmap
+                .unwrap()(self.bitmap, angle, xscale, yscale, &mut alloced_size);
+            ensure!(!ptr.is_null(), {"failed to rotate bitmap"});
+            Ok(Bitmap::from_raw(ptr))
+        }
+    }
+
+    // A view of the mask sharing this bitmap's pixel data, or `None` when
+    // it has no mask.
+    pub fn mask(&self) -> Option<BitmapMask<'_>> {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            let mask = (*graphics).getBitmapMask.unwrap()(self.bitmap);
+            if mask.is_null() {
+                None
+            } else {
+                Some(BitmapMask { inner: BitmapRef::from_raw(mask) })
+            }
+        }
+    }
+
+    // The mask must be the same size as this bitmap.
+    pub fn set_mask(&mut self, mask: &BitmapRef) -> Result<()> {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            let ok = (*graphics).setBitmapMask.unwrap()(self.bitmap, mask.bitmap);
+            ensure!(ok != 0, {"failed to set bitmap mask"});
+            Ok(())
+        }
+    }
+
+    // Pixel-perfect test of the opaque pixels of both bitmaps, limited to
+    // the non-empty `rect`.
+    pub fn overlaps(&self, x1: i32, y1: i32, flip1: BitmapFlip, other: &BitmapRef,
+                    x2: i32, y2: i32, flip2: BitmapFlip, rect: Rect) -> bool {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            (*graphics).checkMaskCollision.unwrap()(
+                self.bitmap, x1, y1, flip1, other.bitmap, x2, y2, flip2, rect) != 0
+        }
+    }
+
+    fn raw_data(&self) -> (usize, usize, usize, bool, *mut u8) {
+        let (mut width, mut height, mut rowbytes, mut hasmask) = (0, 0, 0, 0);
+        let mut data = ptr::null_mut();
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            (*graphics).getBitmapData.unwrap()(
+                self.bitmap, &mut width, &mut height, &mut rowbytes, &mut hasmask, &mut data);
+        }
+        (width as usize, height as usize, rowbytes as usize, hasmask != 0, data)
+    }
+
+    pub fn data(&self) -> BitmapData<&[u8]> {
+        let (width, height, rowbytes, hasmask, data) = self.raw_data();
+        let len = rowbytes * height;
+        unsafe {
+            BitmapData {
+                width,
+                height,
+                rowbytes,
+                data: BitPlane::new(slice::from_raw_parts(data, len), width, height, rowbytes),
+                mask: if hasmask {
+                    Some(BitPlane::new(slice::from_raw_parts(data.add(len), len),
+                                       width, height, rowbytes))
+                } else {
+                    None
+                },
+            }
+        }
+    }
+
+    pub fn data_mut(&mut self) -> BitmapData<&mut [u8]> {
+        let (width, height, rowbytes, hasmask, data) = self.raw_data();
+        let len = rowbytes * height;
+        unsafe {
+            BitmapData {
+                width,
+                height,
+                rowbytes,
+                data: BitPlane::new(slice::from_raw_parts_mut(data, len), width, height, rowbytes),
+                mask: if hasmask {
+                    Some(BitPlane::new(slice::from_raw_parts_mut(data.add(len), len),
+                                       width, height, rowbytes))
+                } else {
+                    None
+                },
+            }
+        }
+    }
+}
+
+// Derefs to `BitmapRef` for shared use only; the methods that need
+// `&mut` are repeated here so the inner reference can never be swapped out.
+pub struct Bitmap {
+    inner: BitmapRef<'static>,
+}
+
+impl Bitmap {
+    fn from_raw(ptr: *mut sys::LCDBitmap) -> Self {
+        Self { inner: BitmapRef::from_raw(ptr) }
+    }
+
+    pub fn new(width: u32, height: u32, bgcolor: Color) -> Result<Self> {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            let ptr = (*graphics).newBitmap
+                .unwrap()(width as i32, height as i32, bgcolor.into());
+            ensure!(!ptr.is_null(), {"failed to create bitmap"});
+            Ok(Self::from_raw(ptr))
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            let c_path = CString::new(path).unwrap();
+            let outerr = ptr::null_mut();
+            let ptr = (*graphics).loadBitmap.unwrap()(c_path.as_ptr(), outerr);
+            ensure!(!ptr.is_null(), {"failed to load bitmap"});
+            Ok(Self::from_raw(ptr))
+        }
+    }
+
+    pub fn copy(bitmap: &BitmapRef) -> Result<Self> {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            let ptr = (*graphics).copyBitmap.unwrap()(bitmap.bitmap);
+            ensure!(!ptr.is_null(), {"failed to copy bitmap"});
+            Ok(Self::from_raw(ptr))
+        }
+    }
+
+    pub fn load_into(&mut self, path: &str) -> Result<()> {
+        self.inner.load_into(path)
+    }
+
+    pub fn clear(&mut self, bgcolor: Color) {
+        self.inner.clear(bgcolor)
+    }
+
+    pub fn set_mask(&mut self, mask: &BitmapRef) -> Result<()> {
+        self.inner.set_mask(mask)
+    }
+
+    pub fn data_mut(&mut self) -> BitmapData<&mut [u8]> {
+        self.inner.data_mut()
+    }
+}
+
+impl Deref for Bitmap {
+    type Target = BitmapRef<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+// Anything `push_context` can draw into.
+pub trait AsBitmapRef {
+    fn as_bitmap_ref(&self) -> &BitmapRef<'_>;
+}
+
+impl AsBitmapRef for BitmapRef<'_> {
+    fn as_bitmap_ref(&self) -> &BitmapRef<'_> {
+        self
+    }
+}
+
+impl AsBitmapRef for Bitmap {
+    fn as_bitmap_ref(&self) -> &BitmapRef<'_> {
+        &self.inner
+    }
 }
 
 impl Drop for Bitmap {
     fn drop(&mut self) {
         unsafe {
             let graphics = Playdate::get_graphics().0;
-            (*graphics).freeBitmap.unwrap()(self.bitmap);
+            (*graphics).freeBitmap.unwrap()(self.inner.bitmap);
+        }
+    }
+}
+
+// The mask of a bitmap. The SDK hands out a new handle that points into
+// the source bitmap's data, so the handle is freed on drop but borrows the
+// source, and only shared access is given since writes would reach it.
+pub struct BitmapMask<'a> {
+    inner: BitmapRef<'a>,
+}
+
+impl<'a> Deref for BitmapMask<'a> {
+    type Target = BitmapRef<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl Drop for BitmapMask<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            let graphics = Playdate::get_graphics().0;
+            (*graphics).freeBitmap.unwrap()(self.inner.bitmap);
+        }
+    }
+}
+
+// A bitmap pre-rotated into `steps` evenly spaced angles, so sprites that
+// turn in fixed increments don't pay for rotatedBitmap every frame.
+pub struct RotationTable {
+    frames: Vec<Bitmap>,
+}
+
+impl RotationTable {
+    pub fn new(bitmap: &BitmapRef, steps: usize) -> Result<Self> {
+        ensure!(steps > 0, {"rotation table needs at least one step"});
+        let mut frames = Vec::with_capacity(steps);
+        frames.push(Bitmap::copy(bitmap)?);
+        for i in 1..steps {
+            frames.push(bitmap.rotated(360.0 * i as f32 / steps as f32, 1.0, 1.0)?);
+        }
+        Ok(Self { frames })
+    }
+
+    pub fn steps(&self) -> usize {
+        self.frames.len()
+    }
+
+    // The step nearest to `angle` degrees, clockwise from upright.
+    pub fn index(&self, angle: f32) -> usize {
+        let steps = self.frames.len() as i64;
+        let t = angle / 360.0 * steps as f32 + 0.5;
+        let mut i = t as i64;
+        if t < i as f32 {
+            i -= 1;
+        }
+        i.rem_euclid(steps) as usize
+    }
+
+    pub fn get(&self, angle: f32) -> &Bitmap {
+        &self.frames[self.index(angle)]
+    }
+
+    pub fn frame(&self, index: usize) -> Option<&Bitmap> {
+        self.frames.get(index)
+    }
+
+    // Rotated frames grow to fit their corners, so draw centered on (x, y).
+    pub fn draw(&self, x: i32, y: i32, angle: f32) {
+        let bitmap = self.get(angle);
+        let (width, height, _, _, _) = bitmap.raw_data();
+        bitmap.draw(x - width as i32 / 2, y - height as i32 / 2, BitmapFlip::kBitmapUnflipped);
+    }
+}
+
+// The mask, when the bitmap has one, is stored by the SDK directly after
+// the image rows with the same row layout.
+pub struct BitmapData<B> {
+    pub width: usize,
+    pub height: usize,
+    pub rowbytes: usize,
+    pub data: BitPlane<B>,
+    pub mask: Option<BitPlane<B>>,
+}
+
+impl<B: AsRef<[u8]>> BitmapData<B> {
+    // Whether the pixel at (x, y) is opaque when drawn with `flip`. A
+    // bitmap without a mask is opaque everywhere.
+    pub fn is_opaque(&self, x: i32, y: i32, flip: BitmapFlip) -> bool {
+        let (width, height) = (self.width as i32, self.height as i32);
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return false;
         }
+        let x = match flip {
+            BitmapFlip::kBitmapFlippedX | BitmapFlip::kBitmapFlippedXY => width - 1 - x,
+            _ => x,
+        };
+        let y = match flip {
+            BitmapFlip::kBitmapFlippedY | BitmapFlip::kBitmapFlippedXY => height - 1 - y,
+            _ => y,
+        };
+        self.mask.as_ref().map_or(true, |mask| mask.get(x, y))
+    }
+
+    // Same result as `BitmapRef::overlaps`, computed from the pixel data
+    // so it also runs off-device.
+    pub fn overlaps<C: AsRef<[u8]>>(&self, x1: i32, y1: i32, flip1: BitmapFlip,
+                                    other: &BitmapData<C>, x2: i32, y2: i32,
+                                    flip2: BitmapFlip, rect: Rect) -> bool {
+        let left = rect.left.max(x1).max(x2);
+        let right = rect.right.min(x1 + self.width as i32).min(x2 + other.width as i32);
+        let top = rect.top.max(y1).max(y2);
+        let bottom = rect.bottom.min(y1 + self.height as i32).min(y2 + other.height as i32);
+        (top..bottom).any(|y| (left..right).any(|x| {
+            self.is_opaque(x - x1, y - y1, flip1) && other.is_opaque(x - x2, y - y2, flip2)
+        }))
     }
 }
 
@@ -343,10 +1152,10 @@ impl BitmapTable {
         unsafe {
             let graphics = Playdate::get_graphics().0;
             let c_path = CString::new(path).unwrap();
-            let outerr = ptr::null_mut();
+            let mut outerr = ptr::null();
             let ptr = (*graphics).loadBitmapTable
-                .unwrap()(c_path.as_ptr(), outerr);
-            ensure!(!outerr.is_null(), {"failed to load bitmap table"});
+                .unwrap()(c_path.as_ptr(), &mut outerr);
+            ensure!(outerr.is_null() && !ptr.is_null(), {"failed to load bitmap table"});
             Ok(Self { table: ptr })
         }
     }
@@ -355,21 +1164,21 @@ impl BitmapTable {
         unsafe {
             let graphics = Playdate::get_graphics().0;
             let c_path = CString::new(path).unwrap();
-            let outerr = ptr::null_mut();
+            let mut outerr = ptr::null();
             (*graphics).loadIntoBitmapTable
-                .unwrap()(c_path.as_ptr(), self.table, outerr);
-            ensure!(!outerr.is_null(), {"failed to load bitmap table"});
+                .unwrap()(c_path.as_ptr(), self.table, &mut outerr);
+            ensure!(outerr.is_null(), {"failed to load bitmap table"});
             Ok(())
         }
     }
 
-    pub fn get_bitmap(&self, idx: usize) -> Result<Bitmap> {
+    pub fn get_bitmap(&self, idx: usize) -> Result<BitmapRef<'_>> {
          unsafe {
              let graphics = Playdate::get_graphics().0;
              let ptr = (*graphics).getTableBitmap
                  .unwrap()(self.table, idx as i32);
              ensure!(!ptr.is_null(), {"failed to get bitmap"});
-             Ok(Bitmap { bitmap: ptr })
+             Ok(BitmapRef::from_raw(ptr))
          }
     }
 }
@@ -382,3 +1191,125 @@ impl Drop for BitmapTable {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLIPS: [BitmapFlip; 4] = [BitmapFlip::kBitmapUnflipped, BitmapFlip::kBitmapFlippedX,
+                                    BitmapFlip::kBitmapFlippedY, BitmapFlip::kBitmapFlippedXY];
+
+    // 4x2 with a mask opaque at (0, 0), (1, 0) and (3, 1):
+    //   ##..
+    //   ...#
+    const MASK: [u8; 2] = [0b1100_0000, 0b0001_0000];
+
+    fn masked() -> BitmapData<&'static [u8]> {
+        BitmapData {
+            width: 4,
+            height: 2,
+            rowbytes: 1,
+            data: BitPlane::new(&[0, 0][..], 4, 2, 1),
+            mask: Some(BitPlane::new(&MASK[..], 4, 2, 1)),
+        }
+    }
+
+    fn solid(width: usize, height: usize) -> BitmapData<&'static [u8]> {
+        BitmapData {
+            width,
+            height,
+            rowbytes: 1,
+            data: BitPlane::new(&[0; 8][..], width, height, 1),
+            mask: None,
+        }
+    }
+
+    fn opaque_pixels(data: &BitmapData<&[u8]>, flip: BitmapFlip) -> Vec<(i32, i32)> {
+        let mut pixels = Vec::new();
+        for y in -1..=data.height as i32 {
+            for x in -1..=data.width as i32 {
+                if data.is_opaque(x, y, flip) {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn is_opaque_follows_the_mask_and_flip() {
+        let data = masked();
+        assert_eq!(opaque_pixels(&data, FLIPS[0]), [(0, 0), (1, 0), (3, 1)]);
+        assert_eq!(opaque_pixels(&data, FLIPS[1]), [(2, 0), (3, 0), (0, 1)]);
+        assert_eq!(opaque_pixels(&data, FLIPS[2]), [(3, 0), (0, 1), (1, 1)]);
+        assert_eq!(opaque_pixels(&data, FLIPS[3]), [(0, 0), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn without_a_mask_every_pixel_inside_is_opaque() {
+        let data = solid(2, 2);
+        for &flip in &FLIPS {
+            assert_eq!(opaque_pixels(&data, flip), [(0, 0), (1, 0), (0, 1), (1, 1)]);
+        }
+    }
+
+    #[test]
+    fn overlaps_tests_opaque_pixels_only() {
+        let (a, b) = (masked(), solid(2, 2));
+        let everywhere = make_rect(-100, -100, 200, 200);
+        let unflipped = BitmapFlip::kBitmapUnflipped;
+        // The square covers the transparent (2, 0) and (3, 0) and the
+        // opaque (3, 1).
+        assert!(a.overlaps(0, 0, unflipped, &b, 2, 0, unflipped, everywhere));
+        // Bounds that only touch.
+        assert!(!a.overlaps(0, 0, unflipped, &b, 4, 0, unflipped, everywhere));
+        assert!(!a.overlaps(0, 0, unflipped, &b, 0, -2, unflipped, everywhere));
+        // Partial overlap past the top left corner.
+        assert!(a.overlaps(0, 0, unflipped, &b, -1, -1, unflipped, everywhere));
+        assert!(b.overlaps(-1, -1, unflipped, &a, 0, 0, unflipped, everywhere));
+    }
+
+    #[test]
+    fn overlaps_applies_flips() {
+        let (a, b) = (masked(), solid(2, 1));
+        let everywhere = make_rect(-100, -100, 200, 200);
+        let unflipped = BitmapFlip::kBitmapUnflipped;
+        // Row 1, columns 2 and 3: opaque at (3, 1) unflipped only.
+        assert!(a.overlaps(0, 0, FLIPS[0], &b, 2, 1, unflipped, everywhere));
+        assert!(!a.overlaps(0, 0, FLIPS[1], &b, 2, 1, unflipped, everywhere));
+        assert!(!a.overlaps(0, 0, FLIPS[2], &b, 2, 1, unflipped, everywhere));
+        assert!(a.overlaps(0, 0, FLIPS[3], &b, 2, 1, unflipped, everywhere));
+        // Two masks: shifted by one the (1, 0) pixels meet, by two nothing does.
+        assert!(a.overlaps(0, 0, unflipped, &a, 1, 0, unflipped, everywhere));
+        assert!(!a.overlaps(0, 0, unflipped, &a, 2, 0, unflipped, everywhere));
+        // Flipped in X, the top row moves to columns 2 and 3.
+        assert!(!a.overlaps(0, 0, FLIPS[1], &a, 0, 0, unflipped, everywhere));
+        assert!(a.overlaps(0, 0, unflipped, &a, -1, 0, FLIPS[1], everywhere));
+    }
+
+    #[test]
+    fn overlaps_is_limited_to_the_rect() {
+        let (a, b) = (masked(), solid(2, 2));
+        let unflipped = BitmapFlip::kBitmapUnflipped;
+        assert!(a.overlaps(0, 0, unflipped, &b, 2, 0, unflipped, make_rect(3, 1, 1, 1)));
+        // Only row 0 of the overlap, where `a` is transparent.
+        assert!(!a.overlaps(0, 0, unflipped, &b, 2, 0, unflipped, make_rect(0, 0, 4, 1)));
+        // Left of the overlap entirely.
+        assert!(!a.overlaps(0, 0, unflipped, &b, 2, 0, unflipped, make_rect(0, 0, 2, 2)));
+        // An empty rect.
+        assert!(!a.overlaps(0, 0, unflipped, &b, 2, 0, unflipped, make_rect(3, 1, 0, 0)));
+    }
+
+    #[test]
+    fn intersect_narrows_to_the_overlap() {
+        assert_eq!(intersect((0, 0, 10, 10), (5, 2, 10, 4)), (5, 2, 5, 4));
+        assert_eq!(intersect((5, 2, 10, 4), (0, 0, 10, 10)), (5, 2, 5, 4));
+        // A rect inside the active clip is kept whole.
+        assert_eq!(intersect((0, 0, 10, 10), (2, 3, 4, 5)), (2, 3, 4, 5));
+        // Disjoint rects clip everything.
+        let (_, _, width, height) = intersect((0, 0, 4, 4), (6, 0, 4, 4));
+        assert_eq!((width, height), (0, 4));
+        let (_, _, width, height) = intersect((0, 0, 4, 4), (0, 6, 4, 4));
+        assert_eq!((width, height), (4, 0));
+    }
+}
@@ -1,23 +1,1090 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde::de::DeserializeOwned;
 use serde_json_core;
-use anyhow::{Result, Error};
+use serde_json_core::ser;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::{fmt, ptr, slice, str};
+use cstr_core::{CStr, CString};
+use anyhow::{Result, Error, ensure};
+use sys;
+use sys::cty;
 use crate::Playdate;
 use crate::file;
 
+pub use sys::json_value_type as ValueType;
+
 pub fn decode<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let buf = read(path)?;
+    from_slice(&buf)
+}
+
+pub fn read(path: &str) -> Result<Vec<u8>> {
     let mut file = Playdate::get_filesystem()
-        .open(path, file::FileOptions::kFileRead).map_err(Error::msg)?;
-    let mut array: [u8; 1024] = [0; 1024];
-    let len = file.read(&mut array)? as usize;
-    let de = serde_json_core::from_slice(&array[0..len - 1]).map_err(Error::msg)?;
-    Ok(de.0)
+        .open(path, file::FileOptions::kFileRead | file::FileOptions::kFileReadData)?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    ensure!(!buf.is_empty(), "Error empty JSON file {}", path);
+    Ok(buf)
 }
 
 pub fn encode<T: Serialize>(path: &str, obj: T) -> Result<()> {
+    write(path, &to_vec(&obj)?)
+}
+
+pub fn encode_pretty<T: Serialize>(path: &str, obj: T) -> Result<()> {
+    write(path, &prettify(&to_vec(&obj)?))
+}
+
+fn write(path: &str, bytes: &[u8]) -> Result<()> {
     let mut file = Playdate::get_filesystem()
-        .open(path, file::FileOptions::kFileRead).map_err(Error::msg)?;
-    let ser = serde_json_core::to_string::<T, 1024>(&obj).map_err(Error::msg)?;
-    file.write(ser.as_bytes())?;
+        .open(path, file::FileOptions::kFileWrite)?;
+    file.write_all(bytes)?;
+    file.flush()?;
     Ok(())
-}
\ No newline at end of file
+}
+
+// serde-json-core reports neither where a document is malformed nor where
+// a value had the wrong type, so on failure `validate` is run to place
+// syntax errors. Type errors have no position to report.
+pub fn from_slice<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T> {
+    match serde_json_core::from_slice(bytes) {
+        Ok(de) => Ok(de.0),
+        Err(err) => match validate(bytes) {
+            Err(parse_err) => Err(Error::msg(format!("{}: {}", err, parse_err))),
+            Ok(()) => Err(Error::msg(err)),
+        },
+    }
+}
+
+pub fn to_string<T: Serialize + ?Sized>(obj: &T) -> Result<String> {
+    String::from_utf8(to_vec(obj)?).map_err(Error::msg)
+}
+
+pub fn to_string_pretty<T: Serialize + ?Sized>(obj: &T) -> Result<String> {
+    String::from_utf8(prettify(&to_vec(obj)?)).map_err(Error::msg)
+}
+
+pub fn to_vec<T: Serialize + ?Sized>(obj: &T) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.resize(256, 0);
+    loop {
+        match serde_json_core::to_slice(obj, &mut buf) {
+            Ok(len) => {
+                buf.truncate(len);
+                return Ok(buf);
+            }
+            Err(ser::Error::BufferFull) => {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            Err(err) => return Err(Error::msg(err)),
+        }
+    }
+}
+
+// Re-indents the compact output of serde-json-core with two spaces per level.
+fn prettify(compact: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(compact.len() * 2);
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let newline = |out: &mut Vec<u8>, depth: usize| {
+        out.push(b'\n');
+        out.resize(out.len() + depth * 2, b' ');
+    };
+    for (i, &c) in compact.iter().enumerate() {
+        if in_string {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == b'\\' {
+                escaped = true;
+            } else if c == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            b'"' => {
+                in_string = true;
+                out.push(c);
+            }
+            b'{' | b'[' => {
+                out.push(c);
+                if !matches!(compact.get(i + 1), Some(b'}') | Some(b']')) {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            b'}' | b']' => {
+                if !matches!(compact.get(i.wrapping_sub(1)), Some(b'{') | Some(b'[')) {
+                    depth -= 1;
+                    newline(&mut out, depth);
+                }
+                out.push(c);
+            }
+            b',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            b':' => out.extend_from_slice(b": "),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub message: &'static str,
+}
+
+impl ParseError {
+    fn new(bytes: &[u8], offset: usize, message: &'static str) -> Self {
+        let before = &bytes[..offset.min(bytes.len())];
+        let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&b| b != b'\n').count() + 1;
+        Self { offset, line, column, message }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {} column {} (byte {})",
+               self.message, self.line, self.column, self.offset)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Expect {
+    Value,
+    ValueOrEnd,
+    Key,
+    KeyOrEnd,
+    Colon,
+    CommaOrEnd,
+    Done,
+}
+
+// Syntax-only pass over a JSON document. serde-json-core does not report
+// where parsing failed, so this is run on failure to locate the error.
+pub fn validate(bytes: &[u8]) -> core::result::Result<(), ParseError> {
+    let mut pos = 0;
+    let mut stack: Vec<u8> = Vec::new();
+    let mut expect = Expect::Value;
+    let err = |pos, message| Err(ParseError::new(bytes, pos, message));
+    loop {
+        while pos < bytes.len() && matches!(bytes[pos], b' ' | b'\t' | b'\n' | b'\r') {
+            pos += 1;
+        }
+        let peek = bytes.get(pos).copied();
+        match expect {
+            Expect::Done => {
+                return match peek {
+                    None => Ok(()),
+                    Some(_) => err(pos, "trailing characters"),
+                };
+            }
+            Expect::Colon => {
+                if peek != Some(b':') {
+                    return err(pos, "expected ':'");
+                }
+                pos += 1;
+                expect = Expect::Value;
+                continue;
+            }
+            Expect::Key | Expect::KeyOrEnd => {
+                match peek {
+                    Some(b'}') if expect == Expect::KeyOrEnd => {
+                        pos += 1;
+                        stack.pop();
+                    }
+                    Some(b'"') => {
+                        pos = scan_string(bytes, pos)?;
+                        expect = Expect::Colon;
+                        continue;
+                    }
+                    None => return err(pos, "EOF while parsing an object"),
+                    _ => return err(pos, "key must be a string"),
+                }
+            }
+            Expect::CommaOrEnd => {
+                let close = if stack.last() == Some(&b'{') { b'}' } else { b']' };
+                match peek {
+                    Some(b',') => {
+                        pos += 1;
+                        expect = if close == b'}' { Expect::Key } else { Expect::Value };
+                        continue;
+                    }
+                    Some(c) if c == close => {
+                        pos += 1;
+                        stack.pop();
+                    }
+                    None => return err(pos, "EOF while parsing a list or object"),
+                    _ if close == b'}' => return err(pos, "expected ',' or '}'"),
+                    _ => return err(pos, "expected ',' or ']'"),
+                }
+            }
+            Expect::Value | Expect::ValueOrEnd => {
+                match peek {
+                    Some(b']') if expect == Expect::ValueOrEnd => {
+                        pos += 1;
+                        stack.pop();
+                    }
+                    Some(b'{') => {
+                        pos += 1;
+                        stack.push(b'{');
+                        expect = Expect::KeyOrEnd;
+                        continue;
+                    }
+                    Some(b'[') => {
+                        pos += 1;
+                        stack.push(b'[');
+                        expect = Expect::ValueOrEnd;
+                        continue;
+                    }
+                    Some(b'"') => pos = scan_string(bytes, pos)?,
+                    Some(b't') => pos = scan_ident(bytes, pos, b"true")?,
+                    Some(b'f') => pos = scan_ident(bytes, pos, b"false")?,
+                    Some(b'n') => pos = scan_ident(bytes, pos, b"null")?,
+                    Some(b'-') | Some(b'0'..=b'9') => pos = scan_number(bytes, pos)?,
+                    None => return err(pos, "EOF while parsing a value"),
+                    _ => return err(pos, "expected a value"),
+                }
+            }
+        }
+        expect = if stack.is_empty() { Expect::Done } else { Expect::CommaOrEnd };
+    }
+}
+
+fn scan_string(bytes: &[u8], start: usize) -> core::result::Result<usize, ParseError> {
+    let mut pos = start + 1;
+    loop {
+        match bytes.get(pos) {
+            None => return Err(ParseError::new(bytes, pos, "EOF while parsing a string")),
+            Some(b'"') => return Ok(pos + 1),
+            Some(b'\\') => {
+                match bytes.get(pos + 1) {
+                    Some(b'"') | Some(b'\\') | Some(b'/') | Some(b'b') | Some(b'f')
+                        | Some(b'n') | Some(b'r') | Some(b't') => pos += 2,
+                    Some(b'u') => {
+                        let hex = bytes.get(pos + 2..pos + 6).unwrap_or(&[]);
+                        if hex.len() != 4 || !hex.iter().all(u8::is_ascii_hexdigit) {
+                            return Err(ParseError::new(bytes, pos, "invalid unicode escape"));
+                        }
+                        pos += 6;
+                    }
+                    _ => return Err(ParseError::new(bytes, pos, "invalid escape")),
+                }
+            }
+            Some(&c) if c < 0x20 => {
+                return Err(ParseError::new(bytes, pos, "control character in string"));
+            }
+            Some(_) => pos += 1,
+        }
+    }
+}
+
+fn scan_ident(bytes: &[u8], start: usize, ident: &[u8]) -> core::result::Result<usize, ParseError> {
+    if bytes[start..].starts_with(ident) {
+        Ok(start + ident.len())
+    } else {
+        Err(ParseError::new(bytes, start, "expected true, false or null"))
+    }
+}
+
+fn scan_number(bytes: &[u8], start: usize) -> core::result::Result<usize, ParseError> {
+    let digits = |mut pos: usize| {
+        let from = pos;
+        while bytes.get(pos).map_or(false, u8::is_ascii_digit) {
+            pos += 1;
+        }
+        (pos, pos > from)
+    };
+    let invalid = |pos| Err(ParseError::new(bytes, pos, "invalid number"));
+    let mut pos = start;
+    if bytes[pos] == b'-' {
+        pos += 1;
+    }
+    match bytes.get(pos) {
+        Some(b'0') => pos += 1,
+        Some(b'1'..=b'9') => pos = digits(pos).0,
+        _ => return invalid(pos),
+    }
+    if bytes.get(pos) == Some(&b'.') {
+        let (end, any) = digits(pos + 1);
+        if !any {
+            return invalid(end);
+        }
+        pos = end;
+    }
+    if matches!(bytes.get(pos), Some(b'e') | Some(b'E')) {
+        pos += 1;
+        if matches!(bytes.get(pos), Some(b'+') | Some(b'-')) {
+            pos += 1;
+        }
+        let (end, any) = digits(pos);
+        if !any {
+            return invalid(end);
+        }
+        pos = end;
+    }
+    Ok(pos)
+}
+
+#[derive(Copy, Clone)]
+pub struct Json(*const sys::playdate_json);
+
+impl Json {
+    pub fn new(json: *const sys::playdate_json) -> Self {
+        Json(json)
+    }
+
+    pub fn decode_file<V: Visitor>(&self, path: &str, visitor: &mut V) -> Result<()> {
+        let mut file = Playdate::get_filesystem()
+            .open(path, file::FileOptions::kFileRead | file::FileOptions::kFileReadData)?;
+        let reader = sys::json_reader {
+            read: Some(read_file),
+            userdata: &mut file as *mut file::File as *mut cty::c_void,
+        };
+        let mut state = DecodeState { visitor, error: None };
+        let mut decoder = decoder(&mut state);
+        let mut value = sys::json_value::default();
+        let result = unsafe { (*self.0).decode.unwrap()(&mut decoder, reader, &mut value) };
+        state.finish(result)
+    }
+
+    pub fn decode_str<V: Visitor>(&self, text: &str, visitor: &mut V) -> Result<()> {
+        let c_text = CString::new(text).map_err(Error::msg)?;
+        let mut state = DecodeState { visitor, error: None };
+        let mut decoder = decoder(&mut state);
+        let mut value = sys::json_value::default();
+        let result = unsafe {
+            (*self.0).decodeString.unwrap()(&mut decoder, c_text.as_ptr(), &mut value)
+        };
+        state.finish(result)
+    }
+
+    pub fn encoder<'a>(&self, sink: &'a mut dyn fmt::Write, pretty: bool) -> Encoder<'a> {
+        let mut sink = Box::new(Sink { writer: sink, failed: false });
+        let mut encoder = sys::json_encoder::default();
+        unsafe {
+            (*self.0).initEncoder.unwrap()(
+                &mut encoder,
+                Some(write_sink),
+                &mut *sink as *mut Sink as *mut cty::c_void,
+                pretty as i32,
+            );
+        }
+        Encoder { encoder, sink }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    Null,
+    Bool(bool),
+    Integer(i32),
+    Float(f32),
+    String(&'a str),
+    Array,
+    Table,
+}
+
+impl<'a> Value<'a> {
+    unsafe fn from_raw(value: &sys::json_value) -> core::result::Result<Self, str::Utf8Error> {
+        let kind = value.type_ as u8;
+        Ok(if kind == ValueType::kJSONTrue as u8 {
+            Value::Bool(true)
+        } else if kind == ValueType::kJSONFalse as u8 {
+            Value::Bool(false)
+        } else if kind == ValueType::kJSONInteger as u8 {
+            Value::Integer(value.data.intval)
+        } else if kind == ValueType::kJSONFloat as u8 {
+            Value::Float(value.data.floatval)
+        } else if kind == ValueType::kJSONString as u8 {
+            Value::String(c_str(value.data.stringval)?)
+        } else if kind == ValueType::kJSONArray as u8 {
+            Value::Array
+        } else if kind == ValueType::kJSONTable as u8 {
+            Value::Table
+        } else {
+            Value::Null
+        })
+    }
+}
+
+// Callbacks for the SDK's SAX-style decoder. Values are only borrowed for
+// the duration of the callback; sublists are reported through
+// `will_decode_sublist`/`did_decode_sublist` and then as `Value::Array` or
+// `Value::Table` in their parent.
+pub trait Visitor {
+    fn decode_error(&mut self, _error: &str, _line: i32) {}
+    fn will_decode_sublist(&mut self, _name: &str, _kind: ValueType) {}
+    fn should_decode_table_value(&mut self, _key: &str) -> bool { true }
+    fn table_value(&mut self, _key: &str, _value: Value) {}
+    fn should_decode_array_value(&mut self, _pos: i32) -> bool { true }
+    fn array_value(&mut self, _pos: i32, _value: Value) {}
+    fn did_decode_sublist(&mut self, _name: &str, _kind: ValueType) {}
+}
+
+struct DecodeState<'a> {
+    visitor: &'a mut dyn Visitor,
+    error: Option<String>,
+}
+
+impl<'a> DecodeState<'a> {
+    // Keys and strings that are not UTF-8 are not passed to the visitor;
+    // the first one fails the decode instead.
+    fn text<T>(&mut self, text: core::result::Result<T, str::Utf8Error>) -> Option<T> {
+        match text {
+            Ok(text) => Some(text),
+            Err(err) => {
+                self.error.get_or_insert_with(|| format!("Error decoding JSON: {}", err));
+                None
+            }
+        }
+    }
+
+    fn finish(self, result: cty::c_int) -> Result<()> {
+        if let Some(error) = self.error {
+            return Err(Error::msg(error));
+        }
+        ensure!(result != 0, "Error decoding JSON");
+        Ok(())
+    }
+}
+
+fn decoder(state: &mut DecodeState) -> sys::json_decoder {
+    sys::json_decoder {
+        decodeError: Some(decode_error),
+        willDecodeSublist: Some(will_decode_sublist),
+        shouldDecodeTableValueForKey: Some(should_decode_table_value),
+        didDecodeTableValue: Some(did_decode_table_value),
+        shouldDecodeArrayValueAtIndex: Some(should_decode_array_value),
+        didDecodeArrayValue: Some(did_decode_array_value),
+        didDecodeSublist: Some(did_decode_sublist),
+        userdata: state as *mut DecodeState as *mut cty::c_void,
+        returnString: 0,
+        path: ptr::null(),
+    }
+}
+
+unsafe fn c_str<'a>(ptr: *const cty::c_char) -> core::result::Result<&'a str, str::Utf8Error> {
+    if ptr.is_null() {
+        Ok("")
+    } else {
+        CStr::from_ptr(ptr).to_str()
+    }
+}
+
+unsafe fn state<'a>(decoder: *mut sys::json_decoder) -> &'a mut DecodeState<'a> {
+    &mut *((*decoder).userdata as *mut DecodeState)
+}
+
+unsafe extern "C" fn decode_error(decoder: *mut sys::json_decoder,
+                                  error: *const cty::c_char, linenum: cty::c_int) {
+    let state = state(decoder);
+    let error = c_str(error).unwrap_or("invalid UTF-8 in error message");
+    state.visitor.decode_error(error, linenum);
+    state.error = Some(format!("{} at line {}", error, linenum));
+}
+
+unsafe extern "C" fn will_decode_sublist(decoder: *mut sys::json_decoder,
+                                         name: *const cty::c_char, kind: ValueType) {
+    let state = state(decoder);
+    if let Some(name) = state.text(c_str(name)) {
+        state.visitor.will_decode_sublist(name, kind)
+    }
+}
+
+unsafe extern "C" fn should_decode_table_value(decoder: *mut sys::json_decoder,
+                                               key: *const cty::c_char) -> cty::c_int {
+    let state = state(decoder);
+    match state.text(c_str(key)) {
+        Some(key) => state.visitor.should_decode_table_value(key) as cty::c_int,
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn did_decode_table_value(decoder: *mut sys::json_decoder,
+                                            key: *const cty::c_char, value: sys::json_value) {
+    let state = state(decoder);
+    if let (Some(key), Some(value)) = (state.text(c_str(key)), state.text(Value::from_raw(&value))) {
+        state.visitor.table_value(key, value)
+    }
+}
+
+unsafe extern "C" fn should_decode_array_value(decoder: *mut sys::json_decoder,
+                                               pos: cty::c_int) -> cty::c_int {
+    state(decoder).visitor.should_decode_array_value(pos) as cty::c_int
+}
+
+unsafe extern "C" fn did_decode_array_value(decoder: *mut sys::json_decoder,
+                                            pos: cty::c_int, value: sys::json_value) {
+    let state = state(decoder);
+    if let Some(value) = state.text(Value::from_raw(&value)) {
+        state.visitor.array_value(pos, value)
+    }
+}
+
+unsafe extern "C" fn did_decode_sublist(decoder: *mut sys::json_decoder,
+                                        name: *const cty::c_char,
+                                        kind: ValueType) -> *mut cty::c_void {
+    let state = state(decoder);
+    if let Some(name) = state.text(c_str(name)) {
+        state.visitor.did_decode_sublist(name, kind);
+    }
+    ptr::null_mut()
+}
+
+unsafe extern "C" fn read_file(userdata: *mut cty::c_void, buf: *mut u8,
+                               bufsize: cty::c_int) -> cty::c_int {
+    let file = &mut *(userdata as *mut file::File);
+    file.read(slice::from_raw_parts_mut(buf, bufsize as usize)).unwrap_or(0)
+}
+
+struct Sink<'a> {
+    writer: &'a mut dyn fmt::Write,
+    failed: bool,
+}
+
+unsafe extern "C" fn write_sink(userdata: *mut cty::c_void, text: *const cty::c_char,
+                                len: cty::c_int) {
+    let sink = &mut *(userdata as *mut Sink);
+    let bytes = slice::from_raw_parts(text as *const u8, len as usize);
+    let written = str::from_utf8(bytes).map_err(|_| fmt::Error)
+        .and_then(|text| sink.writer.write_str(text));
+    if written.is_err() {
+        sink.failed = true;
+    }
+}
+
+// Incremental encoder writing straight to a `fmt::Write` sink such as a
+// `File`, so large documents never have to be held in memory. Write errors
+// are only reported by `finish`; an encoder dropped without it loses them.
+#[must_use = "call `finish` to learn whether the sink failed"]
+pub struct Encoder<'a> {
+    encoder: sys::json_encoder,
+    sink: Box<Sink<'a>>,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn start_array(&mut self) {
+        unsafe { self.encoder.startArray.unwrap()(&mut self.encoder) }
+    }
+
+    pub fn add_array_member(&mut self) {
+        unsafe { self.encoder.addArrayMember.unwrap()(&mut self.encoder) }
+    }
+
+    pub fn end_array(&mut self) {
+        unsafe { self.encoder.endArray.unwrap()(&mut self.encoder) }
+    }
+
+    pub fn start_table(&mut self) {
+        unsafe { self.encoder.startTable.unwrap()(&mut self.encoder) }
+    }
+
+    pub fn add_table_member(&mut self, name: &str) {
+        unsafe {
+            self.encoder.addTableMember.unwrap()(
+                &mut self.encoder, name.as_ptr() as *const cty::c_char, name.len() as i32)
+        }
+    }
+
+    pub fn end_table(&mut self) {
+        unsafe { self.encoder.endTable.unwrap()(&mut self.encoder) }
+    }
+
+    pub fn write_null(&mut self) {
+        unsafe { self.encoder.writeNull.unwrap()(&mut self.encoder) }
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        unsafe {
+            if value {
+                self.encoder.writeTrue.unwrap()(&mut self.encoder)
+            } else {
+                self.encoder.writeFalse.unwrap()(&mut self.encoder)
+            }
+        }
+    }
+
+    pub fn write_int(&mut self, value: i32) {
+        unsafe { self.encoder.writeInt.unwrap()(&mut self.encoder, value) }
+    }
+
+    pub fn write_double(&mut self, value: f64) {
+        unsafe { self.encoder.writeDouble.unwrap()(&mut self.encoder, value) }
+    }
+
+    pub fn write_string(&mut self, value: &str) {
+        unsafe {
+            self.encoder.writeString.unwrap()(
+                &mut self.encoder, value.as_ptr() as *const cty::c_char, value.len() as i32)
+        }
+    }
+
+    pub fn finish(self) -> Result<()> {
+        ensure!(!self.sink.failed, "Error writing JSON");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use core::fmt::Write;
+    use serde::Deserialize;
+
+    fn error_at(json: &str) -> (usize, usize, &'static str) {
+        let err = validate(json.as_bytes()).unwrap_err();
+        (err.line, err.column, err.message)
+    }
+
+    #[test]
+    fn validate_accepts_documents() {
+        for json in ["{}", "[]", " 1 ", "-0.5e+3", "\"a\\n\\u00e9\"", "null",
+                     "{\"a\": [true, false, null, {\"b\": []}]}", "[1,\n 2]"] {
+            assert_eq!(validate(json.as_bytes()), Ok(()), "{}", json);
+        }
+    }
+
+    #[test]
+    fn validate_locates_syntax_errors() {
+        assert_eq!(error_at(""), (1, 1, "EOF while parsing a value"));
+        assert_eq!(error_at("{\"a\" 1}"), (1, 6, "expected ':'"));
+        assert_eq!(error_at("{1: 2}"), (1, 2, "key must be a string"));
+        assert_eq!(error_at("[1,\n 2 3]"), (2, 4, "expected ',' or ']'"));
+        assert_eq!(error_at("{\"a\": 1 2}"), (1, 9, "expected ',' or '}'"));
+        assert_eq!(error_at("[1, 2"), (1, 6, "EOF while parsing a list or object"));
+        assert_eq!(error_at("[1] x"), (1, 5, "trailing characters"));
+        assert_eq!(error_at("[tru]"), (1, 2, "expected true, false or null"));
+        assert_eq!(error_at("[@]"), (1, 2, "expected a value"));
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Level {
+        name: u32,
+        spawns: [Spawn; 2],
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Spawn {
+        x: i32,
+        y: i32,
+    }
+
+    #[test]
+    fn syntax_errors_report_position() {
+        let json = b"{\"name\": 1,\n \"spawns\": [{\"x\": 1 \"y\": 2}]}";
+        let err = from_slice::<Level>(json).unwrap_err().to_string();
+        assert!(err.ends_with(": expected ',' or '}' at line 2 column 21 (byte 32)"), "{}", err);
+    }
+
+    #[test]
+    fn type_errors_pass_through() {
+        let json = b"{\"name\": \"one\", \"spawns\": []}";
+        let err = from_slice::<Level>(json).unwrap_err().to_string();
+        assert!(!err.contains(" at line "), "{}", err);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Doc<'a> {
+        title: &'a str,
+        scale: f32,
+        offset: f64,
+        flags: [bool; 2],
+        empty: [u8; 0],
+        layers: [Layer<'a>; 2],
+        next: Option<u32>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Layer<'a> {
+        name: &'a str,
+        points: [(i32, i32); 2],
+    }
+
+    fn doc(title: &str) -> Doc<'_> {
+        Doc {
+            title,
+            scale: 0.1,
+            offset: -2.5e-3,
+            flags: [true, false],
+            empty: [],
+            layers: [
+                Layer { name: "{a: [1, 2]}", points: [(1, -2), (30, 400)] },
+                Layer { name: "", points: [(0, 0); 2] },
+            ],
+            next: None,
+        }
+    }
+
+    #[test]
+    fn compact_and_pretty_round_trip() {
+        let doc = doc("level, one");
+        let compact = to_vec(&doc).unwrap();
+        assert_eq!(from_slice::<Doc>(&compact).unwrap(), doc);
+        let pretty = prettify(&compact);
+        assert_eq!(from_slice::<Doc>(&pretty).unwrap(), doc);
+        assert_eq!(str::from_utf8(&pretty).unwrap(), "{
+  \"title\": \"level, one\",
+  \"scale\": 0.1,
+  \"offset\": -0.0025,
+  \"flags\": [
+    true,
+    false
+  ],
+  \"empty\": [],
+  \"layers\": [
+    {
+      \"name\": \"{a: [1, 2]}\",
+      \"points\": [
+        [
+          1,
+          -2
+        ],
+        [
+          30,
+          400
+        ]
+      ]
+    },
+    {
+      \"name\": \"\",
+      \"points\": [
+        [
+          0,
+          0
+        ],
+        [
+          0,
+          0
+        ]
+      ]
+    }
+  ],
+  \"next\": null
+}");
+    }
+
+    // serde-json-core hands strings back without unescaping them.
+    #[test]
+    fn escapes_survive_prettify() {
+        let doc = doc("say \"hi\"\\\n");
+        let compact = to_vec(&doc).unwrap();
+        let pretty = prettify(&compact);
+        assert_eq!(from_slice::<Doc>(&pretty).unwrap().title, "say \\\"hi\\\"\\\\\\n");
+        assert_eq!(from_slice::<Doc>(&compact).unwrap(),
+                   from_slice::<Doc>(&pretty).unwrap());
+    }
+
+    #[test]
+    fn buffer_grows_past_the_first_guess() {
+        let mut rows = [[0i32; 32]; 4];
+        for (i, value) in rows.iter_mut().flatten().enumerate() {
+            *value = -1000 * i as i32;
+        }
+        let compact = to_vec(&rows).unwrap();
+        assert!(compact.len() > 256);
+        assert_eq!(from_slice::<[[i32; 32]; 4]>(&compact).unwrap(), rows);
+    }
+
+    // Logs each callback it sees.
+    #[derive(Default)]
+    struct Log(String);
+
+    impl Visitor for Log {
+        fn decode_error(&mut self, error: &str, line: i32) {
+            write!(self.0, "error({}, {}) ", error, line).unwrap();
+        }
+        fn will_decode_sublist(&mut self, name: &str, _kind: ValueType) {
+            write!(self.0, "open({}) ", name).unwrap();
+        }
+        fn should_decode_table_value(&mut self, key: &str) -> bool {
+            key != "skip"
+        }
+        fn table_value(&mut self, key: &str, value: Value) {
+            write!(self.0, "{}={:?} ", key, value).unwrap();
+        }
+        fn array_value(&mut self, pos: i32, value: Value) {
+            write!(self.0, "[{}]={:?} ", pos, value).unwrap();
+        }
+        fn did_decode_sublist(&mut self, name: &str, _kind: ValueType) {
+            write!(self.0, "close({}) ", name).unwrap();
+        }
+    }
+
+    fn int(value: i32) -> sys::json_value {
+        let mut raw = sys::json_value::default();
+        raw.type_ = ValueType::kJSONInteger as _;
+        raw.data.intval = value;
+        raw
+    }
+
+    fn string(text: &CStr) -> sys::json_value {
+        let mut raw = sys::json_value::default();
+        raw.type_ = ValueType::kJSONString as _;
+        raw.data.stringval = text.as_ptr() as *mut _;
+        raw
+    }
+
+    fn c(bytes: &[u8]) -> &CStr {
+        CStr::from_bytes_with_nul(bytes).unwrap()
+    }
+
+    #[test]
+    fn decoder_callbacks_reach_the_visitor() {
+        let mut log = Log::default();
+        let mut state = DecodeState { visitor: &mut log, error: None };
+        let mut decoder = decoder(&mut state);
+        let table = ValueType::kJSONTable;
+        unsafe {
+            will_decode_sublist(&mut decoder, c(b"_root\0").as_ptr(), table);
+            assert_eq!(should_decode_table_value(&mut decoder, c(b"a\0").as_ptr()), 1);
+            did_decode_table_value(&mut decoder, c(b"a\0").as_ptr(), int(7));
+            assert_eq!(should_decode_table_value(&mut decoder, c(b"skip\0").as_ptr()), 0);
+            did_decode_array_value(&mut decoder, 1, string(c(b"hi\0")));
+            did_decode_sublist(&mut decoder, c(b"_root\0").as_ptr(), table);
+        }
+        assert!(state.finish(1).is_ok());
+        assert_eq!(log.0, "open(_root) a=Integer(7) [1]=String(\"hi\") close(_root) ");
+    }
+
+    #[test]
+    fn invalid_utf8_fails_the_decode() {
+        let mut log = Log::default();
+        let mut state = DecodeState { visitor: &mut log, error: None };
+        let mut decoder = decoder(&mut state);
+        unsafe {
+            assert_eq!(should_decode_table_value(&mut decoder, c(b"\xff\0").as_ptr()), 0);
+            did_decode_table_value(&mut decoder, c(b"\xff\0").as_ptr(), int(1));
+            did_decode_array_value(&mut decoder, 0, string(c(b"a\xc3\0")));
+        }
+        let err = state.finish(1).unwrap_err().to_string();
+        assert!(err.starts_with("Error decoding JSON: invalid utf-8"), "{}", err);
+        assert_eq!(log.0, "");
+    }
+
+    #[test]
+    fn decode_errors_keep_the_line() {
+        let mut log = Log::default();
+        let mut state = DecodeState { visitor: &mut log, error: None };
+        let mut decoder = decoder(&mut state);
+        unsafe { decode_error(&mut decoder, c(b"bad\0").as_ptr(), 3) };
+        assert_eq!(state.finish(0).unwrap_err().to_string(), "bad at line 3");
+        assert_eq!(log.0, "error(bad, 3) ");
+    }
+
+    // Stands in for the SDK encoder: each call writes a token through the
+    // sink, so the output shows what the wrapper called and with what.
+    unsafe fn emit(encoder: *mut sys::json_encoder, text: &str) {
+        (*encoder).writeStringFunc.unwrap()((*encoder).userdata,
+                                            text.as_ptr() as *const cty::c_char,
+                                            text.len() as cty::c_int)
+    }
+
+    unsafe fn slice_str<'a>(text: *const cty::c_char, len: cty::c_int) -> &'a str {
+        str::from_utf8(slice::from_raw_parts(text as *const u8, len as usize)).unwrap()
+    }
+
+    unsafe extern "C" fn start_array(e: *mut sys::json_encoder) { emit(e, "[") }
+    unsafe extern "C" fn add_array_member(e: *mut sys::json_encoder) { emit(e, "+") }
+    unsafe extern "C" fn end_array(e: *mut sys::json_encoder) { emit(e, "]") }
+    unsafe extern "C" fn start_table(e: *mut sys::json_encoder) { emit(e, "{") }
+    unsafe extern "C" fn end_table(e: *mut sys::json_encoder) { emit(e, "}") }
+    unsafe extern "C" fn write_null(e: *mut sys::json_encoder) { emit(e, "null") }
+    unsafe extern "C" fn write_true(e: *mut sys::json_encoder) { emit(e, "true") }
+    unsafe extern "C" fn write_false(e: *mut sys::json_encoder) { emit(e, "false") }
+    unsafe extern "C" fn write_int(e: *mut sys::json_encoder, value: cty::c_int) {
+        emit(e, &format!("{}", value))
+    }
+    unsafe extern "C" fn write_double(e: *mut sys::json_encoder, value: f64) {
+        emit(e, &format!("{}", value))
+    }
+    unsafe extern "C" fn add_table_member(e: *mut sys::json_encoder,
+                                          name: *const cty::c_char, len: cty::c_int) {
+        emit(e, &format!("+{}:", slice_str(name, len)))
+    }
+    unsafe extern "C" fn write_string(e: *mut sys::json_encoder,
+                                      text: *const cty::c_char, len: cty::c_int) {
+        emit(e, &format!("'{}'", slice_str(text, len)))
+    }
+
+    fn fake_encoder(writer: &mut dyn fmt::Write) -> Encoder<'_> {
+        let mut sink = Box::new(Sink { writer, failed: false });
+        let mut encoder = sys::json_encoder::default();
+        encoder.writeStringFunc = Some(write_sink);
+        encoder.userdata = &mut *sink as *mut Sink as *mut cty::c_void;
+        encoder.startArray = Some(start_array);
+        encoder.addArrayMember = Some(add_array_member);
+        encoder.endArray = Some(end_array);
+        encoder.startTable = Some(start_table);
+        encoder.addTableMember = Some(add_table_member);
+        encoder.endTable = Some(end_table);
+        encoder.writeNull = Some(write_null);
+        encoder.writeTrue = Some(write_true);
+        encoder.writeFalse = Some(write_false);
+        encoder.writeInt = Some(write_int);
+        encoder.writeDouble = Some(write_double);
+        encoder.writeString = Some(write_string);
+        Encoder { encoder, sink }
+    }
+
+    #[test]
+    fn encoder_forwards_members_in_order() {
+        let mut out = String::new();
+        let mut encoder = fake_encoder(&mut out);
+        encoder.start_table();
+        encoder.add_table_member("name");
+        encoder.write_string("level, \"one\"");
+        encoder.add_table_member("spawns");
+        encoder.start_array();
+        encoder.add_array_member();
+        encoder.write_int(-3);
+        encoder.add_array_member();
+        encoder.write_double(0.5);
+        encoder.add_array_member();
+        encoder.write_bool(true);
+        encoder.add_array_member();
+        encoder.write_bool(false);
+        encoder.add_array_member();
+        encoder.write_null();
+        encoder.end_array();
+        encoder.end_table();
+        encoder.finish().unwrap();
+        assert_eq!(out, "{+name:'level, \"one\"'+spawns:[+-3+0.5+true+false+null]}");
+    }
+
+    struct Full;
+
+    impl fmt::Write for Full {
+        fn write_str(&mut self, _text: &str) -> fmt::Result {
+            Err(fmt::Error)
+        }
+    }
+
+    #[test]
+    fn encoder_reports_sink_errors_on_finish() {
+        let mut full = Full;
+        let mut encoder = fake_encoder(&mut full);
+        encoder.start_array();
+        encoder.end_array();
+        assert_eq!(encoder.finish().unwrap_err().to_string(), "Error writing JSON");
+    }
+
+    // Feeds `text` to `visitor` in the order the SDK decoder makes its
+    // callbacks, so visitors can be tested on the host. Strings must not
+    // contain escapes.
+    pub(crate) fn drive(text: &str, visitor: &mut dyn Visitor) {
+        let mut sax = Sax { text, pos: 0 };
+        sax.value("_root", visitor, false);
+        assert_eq!(sax.peek(), None, "trailing text");
+    }
+
+    struct Sax<'t> {
+        text: &'t str,
+        pos: usize,
+    }
+
+    impl<'t> Sax<'t> {
+        fn peek(&mut self) -> Option<u8> {
+            let bytes = self.text.as_bytes();
+            while bytes.get(self.pos).map_or(false, u8::is_ascii_whitespace) {
+                self.pos += 1;
+            }
+            bytes.get(self.pos).copied()
+        }
+
+        fn eat(&mut self, c: u8) -> bool {
+            let found = self.peek() == Some(c);
+            if found {
+                self.pos += 1;
+            }
+            found
+        }
+
+        fn string(&mut self) -> &'t str {
+            assert!(self.eat(b'"'), "expected a string at {}", self.pos);
+            let len = self.text[self.pos..].find('"').unwrap();
+            let text = &self.text[self.pos..self.pos + len];
+            self.pos += len + 1;
+            text
+        }
+
+        // Callbacks are only made for values the visitor didn't skip.
+        fn value(&mut self, name: &str, visitor: &mut dyn Visitor, skip: bool) -> Value<'t> {
+            let (open, close, kind, value) = match self.peek().unwrap() {
+                b'{' => (b'{', b'}', ValueType::kJSONTable, Value::Table),
+                b'[' => (b'[', b']', ValueType::kJSONArray, Value::Array),
+                b'"' => return Value::String(self.string()),
+                _ => return self.scalar(),
+            };
+            assert!(self.eat(open));
+            if !skip {
+                visitor.will_decode_sublist(name, kind);
+            }
+            let mut pos = 0;
+            while !self.eat(close) {
+                if pos > 0 {
+                    assert!(self.eat(b','), "expected ',' at {}", self.pos);
+                }
+                if kind == ValueType::kJSONTable {
+                    let key = self.string();
+                    assert!(self.eat(b':'), "expected ':' at {}", self.pos);
+                    let decode = !skip && visitor.should_decode_table_value(key);
+                    let value = self.value(key, visitor, !decode);
+                    if decode {
+                        visitor.table_value(key, value);
+                    }
+                } else {
+                    let decode = !skip && visitor.should_decode_array_value(pos);
+                    let value = self.value("", visitor, !decode);
+                    if decode {
+                        visitor.array_value(pos, value);
+                    }
+                }
+                pos += 1;
+            }
+            if !skip {
+                visitor.did_decode_sublist(name, kind);
+            }
+            value
+        }
+
+        fn scalar(&mut self) -> Value<'t> {
+            let start = self.pos;
+            let len = self.text[start..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && !"+-.".contains(c))
+                .unwrap_or(self.text.len() - start);
+            self.pos += len;
+            match &self.text[start..self.pos] {
+                "null" => Value::Null,
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                number if number.contains(|c| ".eE".contains(c)) => {
+                    Value::Float(number.parse().unwrap())
+                }
+                number => Value::Integer(number.parse().unwrap()),
+            }
+        }
+    }
+
+    #[test]
+    fn drive_walks_nested_values() {
+        let mut log = Log::default();
+        drive("{\"a\": [1, 2.5, {\"skip\": [3]}], \"b\": \"x\"}", &mut log);
+        assert_eq!(log.0, "open(_root) open(a) [0]=Integer(1) [1]=Float(2.5) open() \
+                           close() [2]=Table close(a) a=Array b=String(\"x\") close(_root) ");
+    }
+}
@@ -1,16 +1,29 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(alloc_error_handler, core_intrinsics, rustc_private)]
 
 pub extern crate playdate_sys as sys;
 use anyhow::Result;
 use sys::PlaydateAPI;
+pub mod animation;
+pub mod bitplane;
+pub mod camera;
 pub mod display;
+pub mod dither;
+#[cfg(feature = "embedded-graphics")]
+pub mod embedded;
 pub mod file;
 pub mod graphics;
 pub mod json;
+mod math;
+pub mod particles;
+pub mod save;
+pub mod scene;
 pub mod sound;
 pub mod sprite;
 pub mod system;
+pub mod text;
+pub mod tilemap;
+pub mod ui;
 
 extern crate alloc;
 extern crate compiler_builtins;
@@ -26,6 +39,7 @@ pub struct Playdate {
     sprite: Option<sprite::PDSprite>,
     display: Option<display::Display>,
     sound: Option<sound::Sound>,
+    json: Option<json::Json>,
 }
 
 impl Playdate {
@@ -50,6 +64,9 @@ impl Playdate {
     pub fn sound(&self) -> sound::Sound {
         self.sound.unwrap().clone()
     }
+    pub fn json(&self) -> json::Json {
+        self.json.unwrap().clone()
+    }
 
     pub fn get_system() -> system::System {
         unsafe { PLAYDATE.system.unwrap().clone() }
@@ -74,6 +91,10 @@ impl Playdate {
     pub fn get_sprite() -> sprite::PDSprite {
         unsafe { PLAYDATE.sprite.unwrap().clone() }
     }
+
+    pub fn get_json() -> json::Json {
+        unsafe { PLAYDATE.json.unwrap().clone() }
+    }
 }
 
 static mut PLAYDATE: Playdate = Playdate {
@@ -83,6 +104,7 @@ static mut PLAYDATE: Playdate = Playdate {
     sprite: None,
     display: None,
     sound: None,
+    json: None,
 };
 
 impl Playdate {
@@ -95,6 +117,7 @@ impl Playdate {
                 sprite: Some(sprite::PDSprite::new((*playdate).sprite)),
                 display: Some(display::Display::new((*playdate).display)),
                 sound: Some(sound::Sound::new((*playdate).sound)),
+                json: Some(json::Json::new((*playdate).json)),
             }
         }
     }
@@ -103,6 +126,9 @@ impl Playdate {
 pub trait Game {
     fn init(playdate: &mut Playdate) -> Self;
     fn update(&mut self, playdate: &mut Playdate) -> Result<()>;
+    // Every system event after `kEventInit`: pause, resume, lock, unlock,
+    // low power, key presses in the simulator and termination.
+    fn event(&mut self, _playdate: &mut Playdate, _event: system::SystemEvent, _arg: u32) {}
 }
 
 #[macro_export]
@@ -115,10 +141,9 @@ macro_rules! start_game {
 
         extern "C" fn update(_ud: *mut cty::c_void) -> cty::c_int {
             unsafe {
-                STATE = STATE.map(|mut s| {
-                    s.update(&mut Playdate::playdate()).unwrap();
-                    s
-                });
+                if let Some(state) = STATE.as_mut() {
+                    state.update(&mut Playdate::playdate()).unwrap();
+                }
             }
             1
         }
@@ -127,7 +152,7 @@ macro_rules! start_game {
         unsafe extern "C" fn eventHandler(
             playdate: *mut PlaydateAPI,
             event: PDSystemEvent,
-            _arg: u32,
+            arg: u32,
         ) -> cty::c_int {
             if event == PDSystemEvent::kEventInit {
                 Playdate::new(playdate);
@@ -136,6 +161,8 @@ macro_rules! start_game {
                 unsafe {
                     STATE = Some($state::init(&mut Playdate::playdate()));
                 }
+            } else if let Some(state) = STATE.as_mut() {
+                state.event(&mut Playdate::playdate(), event, arg);
             }
             0
         }
@@ -160,23 +187,28 @@ unsafe impl GlobalAlloc for PlaydateAllocator {
     }
 }
 
+#[cfg(not(test))]
 #[global_allocator]
 static mut A: PlaydateAllocator = PlaydateAllocator;
 
+#[cfg(not(test))]
 #[alloc_error_handler]
 fn alloc_error(_layout: Layout) -> ! {
     Playdate::get_system().log_to_console("OOM\0");
     abort_with_addr(0xDEADFA11)
 }
 
+#[cfg(not(test))]
 fn abort_with_addr(addr: usize) -> ! {
     let p = addr as *mut i32;
     unsafe { *p = 0 };
     core::intrinsics::abort()
 }
 
+#[cfg(not(test))]
 use core::panic::PanicInfo;
 
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(panic_info: &PanicInfo) -> ! {
     use core::fmt::Write;
@@ -0,0 +1,17 @@
+// Float functions missing from core, backed by compiler intrinsics.
+
+pub(crate) fn sqrt(x: f32) -> f32 {
+    unsafe { core::intrinsics::sqrtf32(x) }
+}
+
+pub(crate) fn floor(x: f32) -> f32 {
+    unsafe { core::intrinsics::floorf32(x) }
+}
+
+pub(crate) fn sin(x: f32) -> f32 {
+    unsafe { core::intrinsics::sinf32(x) }
+}
+
+pub(crate) fn cos(x: f32) -> f32 {
+    unsafe { core::intrinsics::cosf32(x) }
+}
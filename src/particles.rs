@@ -0,0 +1,485 @@
+use alloc::vec::Vec;
+use anyhow::Result;
+use crate::graphics::{Bitmap, BitmapFlip, BitmapRef, Color, Graphics, Pattern, SolidColor};
+use crate::math;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Particle {
+    pub x: f32,
+    pub y: f32,
+    pub vx: f32,
+    pub vy: f32,
+    pub age: u32,
+    pub lifetime: u32,
+}
+
+impl Particle {
+    // How far through its life the particle is, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        (self.age as f32 / self.lifetime.max(1) as f32).min(1.0)
+    }
+}
+
+// Particles in storage reserved up front. Spawning into a full pool fails
+// instead of growing it, and dead particles are swapped out, so a running
+// effect never goes back to the allocator.
+pub struct Pool {
+    particles: Vec<Particle>,
+    // As asked for; the vector may have reserved more.
+    capacity: usize,
+}
+
+impl Pool {
+    pub fn new(capacity: usize) -> Self {
+        Self { particles: Vec::with_capacity(capacity), capacity }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn spawn(&mut self, particle: Particle) -> bool {
+        if self.particles.len() >= self.capacity {
+            return false;
+        }
+        self.particles.push(particle);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, Particle> {
+        self.particles.iter()
+    }
+
+    // Ages and moves every particle by `dt_ms` under `gravity` (pixels per
+    // second squared) and drops the ones that have expired.
+    pub fn update(&mut self, dt_ms: u32, gravity: (f32, f32)) {
+        let dt = dt_ms as f32 / 1000.0;
+        let mut i = 0;
+        while i < self.particles.len() {
+            let particle = &mut self.particles[i];
+            particle.age += dt_ms;
+            if particle.age >= particle.lifetime {
+                self.particles.swap_remove(i);
+                continue;
+            }
+            particle.vx += gravity.0 * dt;
+            particle.vy += gravity.1 * dt;
+            particle.x += particle.vx * dt;
+            particle.y += particle.vy * dt;
+            i += 1;
+        }
+    }
+}
+
+// Copies of a bitmap with progressively more of its mask dithered away,
+// from the faintest to the original.
+pub struct FadeTable {
+    frames: Vec<Bitmap>,
+}
+
+impl FadeTable {
+    pub fn new(bitmap: &BitmapRef, levels: usize) -> Result<Self> {
+        let levels = levels.max(1);
+        let graphics = crate::Playdate::get_graphics();
+        let (width, height) = {
+            let data = bitmap.data();
+            (data.width, data.height)
+        };
+        let mut frames = Vec::with_capacity(levels);
+        for i in 0..levels {
+            let mut frame = Bitmap::new(width as u32, height as u32,
+                                        Color::SolidColor(SolidColor::kColorClear))?;
+            {
+                let _target = graphics.push_context(&mut frame);
+                bitmap.draw(0, 0, BitmapFlip::kBitmapUnflipped);
+            }
+            let coverage = Pattern::bayer8((64 * (i + 1) / levels) as u8).bitmap();
+            if let Some(mask) = frame.data_mut().mask.as_mut() {
+                for y in 0..height as i32 {
+                    for x in 0..width as i32 {
+                        if coverage[y as usize % 8] & (0x80 >> (x % 8)) == 0 {
+                            mask.set(x, y, false);
+                        }
+                    }
+                }
+            }
+            frames.push(frame);
+        }
+        Ok(Self { frames })
+    }
+
+    // The frame for `alpha` from 0 (gone) to 1 (the original).
+    pub fn get(&self, alpha: f32) -> &Bitmap {
+        let last = self.frames.len() - 1;
+        let index = (alpha * self.frames.len() as f32) as usize;
+        &self.frames[index.min(last)]
+    }
+}
+
+pub enum Shape {
+    Circle,
+    Square,
+    Bitmap(FadeTable),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Emission {
+    // Particles at once, each time the emitter starts.
+    Burst(usize),
+    // Particles per second while running.
+    Continuous(f32),
+}
+
+// Spawns particles into its pool and draws them. Angles are in degrees
+// with 0 pointing up and 90 to the right, like the crank; speeds are in
+// pixels per second and lifetimes in milliseconds. Each random property is
+// picked uniformly from its (min, max) range. Sizes are diameters for
+// circles and squares and scale factors for bitmaps, going from the first
+// value at birth to the second at death. With fading on, particles are
+// dithered away over their lifetime.
+pub struct Emitter {
+    pool: Pool,
+    x: f32,
+    y: f32,
+    emission: Emission,
+    running: bool,
+    pending: f32,
+    angle: (f32, f32),
+    speed: (f32, f32),
+    gravity: (f32, f32),
+    lifetime: (u32, u32),
+    size: (f32, f32),
+    color: SolidColor,
+    fade: bool,
+    shape: Shape,
+    seed: u32,
+}
+
+impl Emitter {
+    pub fn new(capacity: usize, emission: Emission) -> Self {
+        Self {
+            pool: Pool::new(capacity),
+            x: 0.0,
+            y: 0.0,
+            emission,
+            running: false,
+            pending: 0.0,
+            angle: (0.0, 360.0),
+            speed: (20.0, 60.0),
+            gravity: (0.0, 0.0),
+            lifetime: (500, 1000),
+            size: (4.0, 0.0),
+            color: SolidColor::kColorBlack,
+            fade: false,
+            shape: Shape::Circle,
+            seed: 0x2545_f491,
+        }
+    }
+
+    pub fn with_angle(mut self, min: f32, max: f32) -> Self {
+        self.angle = (min, max);
+        self
+    }
+
+    pub fn with_speed(mut self, min: f32, max: f32) -> Self {
+        self.speed = (min, max);
+        self
+    }
+
+    pub fn with_gravity(mut self, x: f32, y: f32) -> Self {
+        self.gravity = (x, y);
+        self
+    }
+
+    pub fn with_lifetime(mut self, min: u32, max: u32) -> Self {
+        self.lifetime = (min, max);
+        self
+    }
+
+    pub fn with_size(mut self, start: f32, end: f32) -> Self {
+        self.size = (start, end);
+        self
+    }
+
+    pub fn with_color(mut self, color: SolidColor) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_fade(mut self, fade: bool) -> Self {
+        self.fade = fade;
+        self
+    }
+
+    pub fn with_shape(mut self, shape: Shape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed.max(1);
+        self
+    }
+
+    pub fn position(&self) -> (f32, f32) {
+        (self.x, self.y)
+    }
+
+    pub fn set_position(&mut self, x: f32, y: f32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // Nothing left to emit or draw.
+    pub fn is_finished(&self) -> bool {
+        !self.running && self.pool.is_empty()
+    }
+
+    pub fn start(&mut self) {
+        match self.emission {
+            Emission::Burst(count) => self.burst(count),
+            Emission::Continuous(_) => {
+                self.running = true;
+                self.pending = 0.0;
+            }
+        }
+    }
+
+    // Stops emitting; live particles play out.
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.pool.clear();
+    }
+
+    // Spawns up to `count` particles now, as many as the pool has room for.
+    pub fn burst(&mut self, count: usize) {
+        for _ in 0..count {
+            let particle = self.particle();
+            if !self.pool.spawn(particle) {
+                break;
+            }
+        }
+    }
+
+    pub fn update(&mut self, dt_ms: u32) {
+        self.pool.update(dt_ms, self.gravity);
+        if let (true, Emission::Continuous(rate)) = (self.running, self.emission) {
+            self.pending += rate * dt_ms as f32 / 1000.0;
+            let count = self.pending as usize;
+            self.pending -= count as f32;
+            self.burst(count);
+        }
+    }
+
+    pub fn draw(&self, graphics: &Graphics) {
+        for particle in self.pool.iter() {
+            let t = particle.progress();
+            let size = self.size.0 + (self.size.1 - self.size.0) * t;
+            let alpha = if self.fade { 1.0 - t } else { 1.0 };
+            let (x, y) = (particle.x as i32, particle.y as i32);
+            match &self.shape {
+                Shape::Bitmap(table) => {
+                    let bitmap = table.get(alpha);
+                    let data = bitmap.data();
+                    let (w, h) = (data.width as f32 * size, data.height as f32 * size);
+                    if w < 1.0 || h < 1.0 {
+                        continue;
+                    }
+                    let (left, top) = (x - w as i32 / 2, y - h as i32 / 2);
+                    if size == 1.0 {
+                        bitmap.draw(left, top, BitmapFlip::kBitmapUnflipped);
+                    } else {
+                        bitmap.draw_scaled(left, top, size, size);
+                    }
+                }
+                shape => {
+                    let d = size as i32;
+                    if d < 1 {
+                        continue;
+                    }
+                    let fade = self.fade_pattern(alpha);
+                    let color = match &fade {
+                        Some(pattern) => Color::Pattern(pattern),
+                        None => Color::SolidColor(self.color),
+                    };
+                    if let Shape::Circle = shape {
+                        graphics.fill_ellipse(x - d / 2, y - d / 2, d, d, 0.0, 0.0, color);
+                    } else {
+                        graphics.fill_rect(x - d / 2, y - d / 2, d, d, color);
+                    }
+                }
+            }
+        }
+    }
+
+    // The emitter's color drawn only where the Bayer pattern for `alpha` is
+    // set. XOR and clear have no pattern equivalent and stay solid.
+    fn fade_pattern(&self, alpha: f32) -> Option<Pattern> {
+        let bits = match self.color {
+            SolidColor::kColorBlack => 0x00,
+            SolidColor::kColorWhite => 0xFF,
+            _ => return None,
+        };
+        if alpha >= 1.0 {
+            return None;
+        }
+        let coverage = Pattern::bayer8((alpha * 64.0 + 0.5) as u8).bitmap();
+        Some(Pattern::with_mask([bits; 8], coverage))
+    }
+
+    fn particle(&mut self) -> Particle {
+        let angle = self.random(self.angle.0, self.angle.1).to_radians();
+        let speed = self.random(self.speed.0, self.speed.1);
+        let lifetime = self.random(self.lifetime.0 as f32, self.lifetime.1 as f32) as u32;
+        Particle {
+            x: self.x,
+            y: self.y,
+            vx: math::sin(angle) * speed,
+            vy: -math::cos(angle) * speed,
+            age: 0,
+            lifetime,
+        }
+    }
+
+    // xorshift32; plenty for effects and free of any allocation or SDK call.
+    fn random(&mut self, min: f32, max: f32) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        min + (max - min) * ((self.seed >> 8) as f32 / (1 << 24) as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn particle(x: f32, lifetime: u32) -> Particle {
+        Particle { x, lifetime, ..Particle::default() }
+    }
+
+    #[test]
+    fn pool_holds_exactly_its_capacity() {
+        let mut pool = Pool::new(3);
+        assert_eq!(pool.capacity(), 3);
+        for i in 0..3 {
+            assert!(pool.spawn(particle(i as f32, 100)));
+        }
+        assert!(!pool.spawn(particle(3.0, 100)));
+        assert_eq!(pool.len(), 3);
+        pool.clear();
+        assert!(pool.is_empty());
+        assert!(!Pool::new(0).spawn(particle(0.0, 100)));
+    }
+
+    #[test]
+    fn update_removes_expired_without_skipping_the_swapped_in() {
+        let mut pool = Pool::new(4);
+        pool.spawn(Particle { vx: 8.0, ..particle(0.0, 500) });
+        pool.spawn(particle(1.0, 250));
+        pool.spawn(Particle { vx: 8.0, ..particle(2.0, 1000) });
+        pool.update(250, (0.0, 0.0));
+        // The last particle took the expired one's slot and still moved.
+        let ages: Vec<(f32, u32)> = pool.iter().map(|p| (p.x, p.age)).collect();
+        assert_eq!(ages, [(2.0, 250), (4.0, 250)]);
+        pool.update(250, (0.0, 0.0));
+        let ages: Vec<(f32, u32)> = pool.iter().map(|p| (p.x, p.age)).collect();
+        assert_eq!(ages, [(6.0, 500)]);
+        pool.update(500, (0.0, 0.0));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn update_integrates_gravity() {
+        let mut pool = Pool::new(1);
+        pool.spawn(Particle { vx: 10.0, ..particle(0.0, 1000) });
+        pool.update(500, (0.0, 100.0));
+        let p = pool.iter().next().unwrap();
+        assert_eq!((p.vx, p.vy, p.x, p.y), (10.0, 50.0, 5.0, 25.0));
+        assert_eq!(p.progress(), 0.5);
+    }
+
+    #[test]
+    fn continuous_emission_carries_fractions() {
+        let mut emitter = Emitter::new(100, Emission::Continuous(10.0));
+        emitter.update(1000);
+        assert!(emitter.pool().is_empty());
+        emitter.start();
+        let mut counts = [0; 4];
+        for (count, dt_ms) in counts.iter_mut().zip([50, 50, 250, 50]) {
+            emitter.update(dt_ms);
+            *count = emitter.pool().len();
+        }
+        assert_eq!(counts, [0, 1, 3, 4]);
+        emitter.stop();
+        emitter.update(1000);
+        assert!(emitter.is_finished());
+    }
+
+    #[test]
+    fn bursts_stop_at_capacity() {
+        let mut emitter = Emitter::new(5, Emission::Burst(8));
+        emitter.start();
+        assert_eq!(emitter.pool().len(), 5);
+        emitter.burst(3);
+        assert_eq!(emitter.pool().len(), 5);
+        assert!(!emitter.is_running());
+
+        let mut emitter = Emitter::new(2, Emission::Continuous(1000.0));
+        emitter.start();
+        emitter.update(100);
+        assert_eq!(emitter.pool().len(), 2);
+    }
+
+    #[test]
+    fn particles_follow_the_ranges() {
+        let mut emitter = Emitter::new(50, Emission::Burst(50))
+            .with_angle(90.0, 90.0)
+            .with_speed(30.0, 30.0)
+            .with_lifetime(200, 400)
+            .with_seed(7);
+        emitter.set_position(5.0, 6.0);
+        emitter.start();
+        for p in emitter.pool().iter() {
+            assert_eq!((p.x, p.y), (5.0, 6.0));
+            // 90 degrees points right, like the crank.
+            assert!((p.vx - 30.0).abs() < 1e-4 && p.vy.abs() < 1e-4, "{:?}", p);
+            assert!((200..=400).contains(&p.lifetime), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn fading_dithers_black_and_white_only() {
+        let emitter = Emitter::new(1, Emission::Burst(1));
+        assert!(emitter.fade_pattern(1.0).is_none());
+        let gone = emitter.fade_pattern(0.0).unwrap();
+        assert_eq!((gone.bitmap(), gone.mask()), ([0; 8], [0; 8]));
+        let half = emitter.fade_pattern(0.5).unwrap();
+        assert_eq!(half.mask(), Pattern::bayer8(32).bitmap());
+        let xor = Emitter::new(1, Emission::Burst(1)).with_color(SolidColor::kColorXOR);
+        assert!(xor.fade_pattern(0.5).is_none());
+    }
+}
@@ -0,0 +1,522 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::format;
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use anyhow::{Result, anyhow, ensure};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use crate::Playdate;
+use crate::file::{FileOptions, Filesystem};
+use crate::json;
+
+const MAGIC: &[u8; 4] = b"PDSV";
+const HEADER_LEN: usize = 16;
+
+// Everything the save store needs from a filesystem. Implemented for the
+// SDK filesystem and for `MemoryStorage`, so the write/rename/recovery
+// sequence can be exercised on the host.
+pub trait Storage {
+    fn read(&self, path: &str) -> Result<Vec<u8>>;
+    fn write(&self, path: &str, data: &[u8]) -> Result<()>;
+    fn rename(&self, from: &str, to: &str) -> Result<()>;
+    fn remove(&self, path: &str) -> Result<()>;
+    fn exists(&self, path: &str) -> bool;
+}
+
+impl Storage for Filesystem {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let mut file = self.open(path, FileOptions::kFileReadData)?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        let mut file = self.open(path, FileOptions::kFileWrite)?;
+        file.write_all(data)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        Filesystem::rename(self, from, to)
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        self.unlink(path, false)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        Filesystem::exists(self, path)
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for &S {
+    fn read(&self, path: &str) -> Result<Vec<u8>> { (**self).read(path) }
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> { (**self).write(path, data) }
+    fn rename(&self, from: &str, to: &str) -> Result<()> { (**self).rename(from, to) }
+    fn remove(&self, path: &str) -> Result<()> { (**self).remove(path) }
+    fn exists(&self, path: &str) -> bool { (**self).exists(path) }
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: RefCell<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        self.files.borrow().get(path).cloned()
+    }
+
+    pub fn insert(&self, path: &str, data: &[u8]) {
+        self.files.borrow_mut().insert(String::from(path), data.to_vec());
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        self.get(path).ok_or_else(|| anyhow!("Error open {}", path))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.insert(path, data);
+        Ok(())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let mut files = self.files.borrow_mut();
+        let data = files.remove(from).ok_or_else(|| anyhow!("Error rename {}", from))?;
+        files.insert(String::from(to), data);
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        self.files.borrow_mut().remove(path).map(|_| ()).ok_or_else(|| anyhow!("Error unlink {}", path))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files.borrow().contains_key(path)
+    }
+}
+
+pub struct Save {
+    pub version: u32,
+    pub data: Vec<u8>,
+    pub from_backup: bool,
+}
+
+// A single save file written atomically: the payload goes to `<path>.tmp`,
+// the previous generation is moved to `<path>.bak`, then the temp file is
+// renamed over `<path>`. Every generation carries a version and CRC32 header
+// so a torn or corrupted file is detected. Loading tries `<path>`, then a
+// complete `<path>.tmp` left by a crash between the two renames, then the
+// backup. Only a generation that decodes is ever rotated into the backup.
+pub struct SaveStore<S: Storage = Filesystem> {
+    storage: S,
+    path: String,
+    version: u32,
+}
+
+impl SaveStore<Filesystem> {
+    pub fn open(path: &str, version: u32) -> Self {
+        Self::new(Playdate::get_filesystem(), path, version)
+    }
+}
+
+impl<S: Storage> SaveStore<S> {
+    pub fn new(storage: S, path: &str, version: u32) -> Self {
+        Self { storage, path: String::from(path), version }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn backup_path(&self) -> String {
+        format!("{}.bak", self.path)
+    }
+
+    fn temp_path(&self) -> String {
+        format!("{}.tmp", self.path)
+    }
+
+    pub fn exists(&self) -> bool {
+        self.storage.exists(&self.path) || self.storage.exists(&self.temp_path())
+            || self.storage.exists(&self.backup_path())
+    }
+
+    pub fn save(&self, data: &[u8]) -> Result<()> {
+        let tmp = self.temp_path();
+        let bak = self.backup_path();
+        let previous = if self.read(&self.path).is_ok() {
+            Some(self.path.clone())
+        } else if self.read(&tmp).is_ok() {
+            Some(tmp.clone())
+        } else {
+            None
+        };
+        if let Some(previous) = previous {
+            if self.storage.exists(&bak) {
+                self.storage.remove(&bak)?;
+            }
+            self.storage.rename(&previous, &bak)?;
+        }
+        self.storage.write(&tmp, &encode(self.version, data))?;
+        self.storage.rename(&tmp, &self.path)
+    }
+
+    pub fn load(&self) -> Result<Save> {
+        // The primary's error is the one reported if nothing else loads.
+        let err = match self.read(&self.path) {
+            Ok((version, data)) => return Ok(Save { version, data, from_backup: false }),
+            Err(err) => err,
+        };
+        if let Ok((version, data)) = self.read(&self.temp_path()) {
+            return Ok(Save { version, data, from_backup: false });
+        }
+        let bytes = self.storage.read(&self.backup_path()).map_err(|_| err)?;
+        let (version, data) = decode(&bytes)?;
+        Ok(Save { version, data, from_backup: true })
+    }
+
+    fn read(&self, path: &str) -> Result<(u32, Vec<u8>)> {
+        self.storage.read(path).and_then(|bytes| decode(&bytes))
+    }
+
+    pub fn delete(&self) -> Result<()> {
+        for path in [self.path.clone(), self.backup_path(), self.temp_path()].iter() {
+            if self.storage.exists(path) {
+                self.storage.remove(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotMeta {
+    pub version: u32,
+    pub timestamp: u32,
+    pub playtime: u32,
+}
+
+// Upgrades a slot's JSON from one schema version to a later one.
+pub type Migration = fn(&[u8]) -> Result<Vec<u8>>;
+
+// Numbered JSON save slots. Slot `n` lives in `<prefix><n>.sav`, written
+// through `SaveStore` as one file holding the metadata and the data, so
+// the two can never get out of step. Slots saved under an older schema
+// version are upgraded on load by chaining the registered migrations up to
+// the current version, and written back upgraded.
+pub struct SaveSlots<T, S: Storage = Filesystem> {
+    storage: S,
+    prefix: String,
+    count: usize,
+    version: u32,
+    migrations: Vec<(u32, u32, Migration)>,
+    clock: fn() -> u32,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> SaveSlots<T, Filesystem> {
+    pub fn open(prefix: &str, count: usize, version: u32) -> Self {
+        Self::new(Playdate::get_filesystem(), prefix, count, version)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, S: Storage> SaveSlots<T, S> {
+    pub fn new(storage: S, prefix: &str, count: usize, version: u32) -> Self {
+        Self {
+            storage,
+            prefix: String::from(prefix),
+            count,
+            version,
+            migrations: Vec::new(),
+            clock: system_clock,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn with_migration(mut self, from: u32, to: u32, migration: Migration) -> Self {
+        assert!(from < to, "migration must go to a later version");
+        self.migrations.retain(|(version, _, _)| *version != from);
+        self.migrations.push((from, to, migration));
+        self
+    }
+
+    pub fn with_clock(mut self, clock: fn() -> u32) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    fn store(&self, slot: usize) -> Result<SaveStore<&S>> {
+        ensure!(slot < self.count, "save slot {} out of range", slot);
+        Ok(SaveStore::new(&self.storage, &format!("{}{}.sav", self.prefix, slot), self.version))
+    }
+
+    pub fn save(&self, slot: usize, obj: &T, playtime: u32) -> Result<SlotMeta> {
+        let meta = SlotMeta { version: self.version, timestamp: (self.clock)(), playtime };
+        self.write(slot, &meta, &json::to_vec(obj)?)?;
+        Ok(meta)
+    }
+
+    fn write(&self, slot: usize, meta: &SlotMeta, data: &[u8]) -> Result<()> {
+        let meta = json::to_vec(meta)?;
+        let mut payload = Vec::with_capacity(4 + meta.len() + data.len());
+        payload.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+        payload.extend_from_slice(&meta);
+        payload.extend_from_slice(data);
+        self.store(slot)?.save(&payload)
+    }
+
+    fn read(&self, slot: usize) -> Result<(SlotMeta, Vec<u8>)> {
+        let save = self.store(slot)?.load()?;
+        let payload = &save.data;
+        ensure!(payload.len() >= 4, "save slot {} truncated", slot);
+        let len = read_u32(&payload[..4]) as usize;
+        ensure!(payload.len() >= 4 + len, "save slot {} truncated", slot);
+        let mut meta: SlotMeta = json::from_slice(&payload[4..4 + len])?;
+        // The file header is what was actually written, whatever the
+        // metadata says.
+        meta.version = save.version;
+        Ok((meta, payload[4 + len..].to_vec()))
+    }
+
+    pub fn load(&self, slot: usize) -> Result<T> {
+        let (mut meta, mut data) = self.read(slot)?;
+        if meta.version == self.version {
+            return json::from_slice(&data);
+        }
+        ensure!(meta.version < self.version,
+                "save slot {} has newer version {} than {}", slot, meta.version, self.version);
+        while meta.version < self.version {
+            let (to, migration) = self.migrations.iter()
+                .find(|(from, _, _)| *from == meta.version)
+                .map(|(_, to, migration)| (*to, *migration))
+                .ok_or_else(|| anyhow!("no migration from version {}", meta.version))?;
+            ensure!(to <= self.version,
+                    "migration from version {} goes past version {}", meta.version, self.version);
+            data = migration(&data)?;
+            meta.version = to;
+        }
+        let obj = json::from_slice(&data)?;
+        self.write(slot, &meta, &data)?;
+        Ok(obj)
+    }
+
+    pub fn meta(&self, slot: usize) -> Result<Option<SlotMeta>> {
+        if !self.store(slot)?.exists() {
+            return Ok(None);
+        }
+        Ok(Some(self.read(slot)?.0))
+    }
+
+    // Metadata of every occupied slot. A slot that exists but cannot be
+    // read is an error rather than an empty slot.
+    pub fn list(&self) -> Result<Vec<(usize, SlotMeta)>> {
+        let mut slots = Vec::new();
+        for slot in 0..self.count {
+            if let Some(meta) = self.meta(slot)? {
+                slots.push((slot, meta));
+            }
+        }
+        Ok(slots)
+    }
+
+    pub fn delete(&self, slot: usize) -> Result<()> {
+        self.store(slot)?.delete()
+    }
+}
+
+fn system_clock() -> u32 {
+    Playdate::get_system().get_seconds_since_epoch().0
+}
+
+fn encode(version: u32, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + data.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&version.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+fn decode(bytes: &[u8]) -> Result<(u32, Vec<u8>)> {
+    ensure!(bytes.len() >= HEADER_LEN, "save file truncated");
+    ensure!(&bytes[0..4] == MAGIC, "not a save file");
+    let version = read_u32(&bytes[4..8]);
+    let len = read_u32(&bytes[8..12]) as usize;
+    let crc = read_u32(&bytes[12..16]);
+    let data = &bytes[HEADER_LEN..];
+    ensure!(data.len() == len, "save file length mismatch: expected {}, got {}", len, data.len());
+    ensure!(crc32(data) == crc, "save file checksum mismatch");
+    Ok((version, data.to_vec()))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(storage: &MemoryStorage) -> SaveStore<&MemoryStorage> {
+        SaveStore::new(storage, "game.sav", 3)
+    }
+
+    #[test]
+    fn round_trip() {
+        let storage = MemoryStorage::new();
+        let store = store(&storage);
+        store.save(b"first").unwrap();
+        store.save(b"second").unwrap();
+        let save = store.load().unwrap();
+        assert_eq!(save.version, 3);
+        assert_eq!(save.data, b"second");
+        assert!(!save.from_backup);
+        assert_eq!(decode(&storage.get("game.sav.bak").unwrap()).unwrap().1, b"first");
+        assert!(!storage.exists("game.sav.tmp"));
+    }
+
+    #[test]
+    fn corrupt_primary_falls_back_to_backup() {
+        let storage = MemoryStorage::new();
+        let store = store(&storage);
+        store.save(b"first").unwrap();
+        store.save(b"second").unwrap();
+        storage.insert("game.sav", b"PDSV garbage");
+        let save = store.load().unwrap();
+        assert_eq!(save.data, b"first");
+        assert!(save.from_backup);
+    }
+
+    #[test]
+    fn corrupt_primary_is_not_rotated_over_backup() {
+        let storage = MemoryStorage::new();
+        let store = store(&storage);
+        store.save(b"first").unwrap();
+        store.save(b"second").unwrap();
+        storage.insert("game.sav", b"torn");
+        store.save(b"third").unwrap();
+        assert_eq!(store.load().unwrap().data, b"third");
+        assert_eq!(decode(&storage.get("game.sav.bak").unwrap()).unwrap().1, b"first");
+    }
+
+    #[test]
+    fn bad_crc_is_rejected() {
+        let storage = MemoryStorage::new();
+        let store = store(&storage);
+        store.save(b"payload").unwrap();
+        let mut bytes = storage.get("game.sav").unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        storage.insert("game.sav", &bytes);
+        let err = store.load().err().unwrap();
+        assert!(format!("{}", err).contains("checksum"));
+    }
+
+    #[test]
+    fn crash_between_renames_loads_temp_file() {
+        let storage = MemoryStorage::new();
+        let store = store(&storage);
+        store.save(b"first").unwrap();
+        // The second save got as far as moving the primary to the backup
+        // and writing the temp file.
+        storage.write("game.sav.tmp", &encode(3, b"second")).unwrap();
+        storage.rename("game.sav", "game.sav.bak").unwrap();
+        let save = store.load().unwrap();
+        assert_eq!(save.data, b"second");
+        assert!(!save.from_backup);
+        store.save(b"third").unwrap();
+        assert_eq!(store.load().unwrap().data, b"third");
+        assert_eq!(decode(&storage.get("game.sav.bak").unwrap()).unwrap().1, b"second");
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct V3 {
+        level: u32,
+        coins: u32,
+    }
+
+    fn v1_to_v2(data: &[u8]) -> Result<Vec<u8>> {
+        #[derive(Deserialize)]
+        struct V1 {
+            level: u32,
+        }
+        #[derive(Serialize)]
+        struct V2 {
+            level: u32,
+            gold: u32,
+        }
+        let old: V1 = json::from_slice(data)?;
+        json::to_vec(&V2 { level: old.level, gold: 5 })
+    }
+
+    fn v2_to_v3(data: &[u8]) -> Result<Vec<u8>> {
+        #[derive(Deserialize)]
+        struct V2 {
+            level: u32,
+            gold: u32,
+        }
+        let old: V2 = json::from_slice(data)?;
+        json::to_vec(&V3 { level: old.level, coins: old.gold * 10 })
+    }
+
+    #[test]
+    fn slots_chain_migrations_and_write_back() {
+        let storage = MemoryStorage::new();
+        let old = SaveSlots::<V3, _>::new(&storage, "slot", 2, 1).with_clock(|| 7);
+        let meta = SlotMeta { version: 1, timestamp: 7, playtime: 30 };
+        old.write(0, &meta, br#"{"level":4}"#).unwrap();
+
+        let slots = SaveSlots::<V3, _>::new(&storage, "slot", 2, 3)
+            .with_clock(|| 9)
+            .with_migration(1, 2, v1_to_v2)
+            .with_migration(2, 3, v2_to_v3);
+        assert_eq!(slots.load(0).unwrap(), V3 { level: 4, coins: 50 });
+        let meta = slots.meta(0).unwrap().unwrap();
+        assert_eq!(meta, SlotMeta { version: 3, timestamp: 7, playtime: 30 });
+        assert_eq!(slots.list().unwrap(), vec![(0, meta)]);
+    }
+
+    #[test]
+    fn slots_report_missing_migration_and_bad_slots() {
+        let storage = MemoryStorage::new();
+        let slots = SaveSlots::<V3, _>::new(&storage, "slot", 2, 3).with_clock(|| 0);
+        slots.save(0, &V3 { level: 1, coins: 2 }, 5).unwrap();
+        assert_eq!(slots.load(0).unwrap(), V3 { level: 1, coins: 2 });
+        storage.insert("slot1.sav", b"junk");
+        assert!(slots.list().is_err());
+
+        let newer = SaveSlots::<V3, _>::new(&storage, "slot", 1, 4).with_clock(|| 0);
+        assert!(newer.load(0).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use anyhow::Result;
+use crate::Playdate;
+use crate::graphics::{Bitmap, BitmapFlip, Color, Pattern, SolidColor, COLUMNS, ROWS};
+use crate::system::SystemEvent;
+
+// One screen of a game. `update` runs only for the top scene and returns
+// what the stack should do next; `draw` runs for the top scene and, when
+// it is an overlay, for the scenes beneath it. `pause`/`resume` are called
+// when another scene covers or uncovers this one, and when the system
+// menu opens or closes.
+pub trait Scene {
+    fn enter(&mut self, _playdate: &mut Playdate) {}
+    fn exit(&mut self, _playdate: &mut Playdate) {}
+    fn pause(&mut self, _playdate: &mut Playdate) {}
+    fn resume(&mut self, _playdate: &mut Playdate) {}
+    fn update(&mut self, playdate: &mut Playdate) -> Result<Action>;
+    fn draw(&mut self, playdate: &mut Playdate);
+    // System events other than pause and resume.
+    fn event(&mut self, _playdate: &mut Playdate, _event: SystemEvent, _arg: u32) {}
+    // Overlays are drawn over the scene below instead of replacing it.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
+
+pub enum Action {
+    None,
+    Push(Box<dyn Scene>, Transition),
+    Pop(Transition),
+    Replace(Box<dyn Scene>, Transition),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+// Durations are in milliseconds. A fade goes to black through the 8x8
+// Bayer patterns and back out; a wipe uncovers the new scene with an edge
+// moving in `Direction`; a slide pushes the old scene off that way.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Transition {
+    None,
+    Fade(u32),
+    Wipe(u32, Direction),
+    Slide(u32, Direction),
+}
+
+impl Transition {
+    fn duration(&self) -> u32 {
+        match *self {
+            Transition::None => 0,
+            Transition::Fade(duration)
+            | Transition::Wipe(duration, _)
+            | Transition::Slide(duration, _) => duration,
+        }
+    }
+}
+
+struct Active {
+    transition: Transition,
+    start: u32,
+    // The scene that was popped or replaced, kept alive to be drawn.
+    leaving: Option<Box<dyn Scene>>,
+    // How many scenes of the stack make up the outgoing picture.
+    base: usize,
+    from: Bitmap,
+    to: Bitmap,
+}
+
+// A stack of scenes. Forward `Game::update` to `update` and `Game::event`
+// to `event`. While a transition runs no scene is updated; both pictures
+// are drawn offscreen every frame and combined on screen.
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+    active: Option<Active>,
+}
+
+impl SceneStack {
+    pub fn new(playdate: &mut Playdate, mut scene: Box<dyn Scene>) -> Self {
+        scene.enter(playdate);
+        Self { scenes: alloc::vec![scene], active: None }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.active.is_some()
+    }
+
+    pub fn top(&mut self) -> Option<&mut (dyn Scene + 'static)> {
+        self.scenes.last_mut().map(|scene| scene.as_mut())
+    }
+
+    // A stack change during a transition cuts the running one short.
+    pub fn push(&mut self, playdate: &mut Playdate, mut scene: Box<dyn Scene>,
+                transition: Transition) -> Result<()> {
+        self.active = None;
+        if let Some(top) = self.scenes.last_mut() {
+            top.pause(playdate);
+        }
+        scene.enter(playdate);
+        let base = self.scenes.len();
+        self.scenes.push(scene);
+        self.start(playdate, transition, None, base)
+    }
+
+    // Popping the last scene leaves the stack empty, which draws nothing.
+    pub fn pop(&mut self, playdate: &mut Playdate, transition: Transition) -> Result<()> {
+        self.active = None;
+        let mut leaving = match self.scenes.pop() {
+            Some(scene) => scene,
+            None => return Ok(()),
+        };
+        leaving.exit(playdate);
+        if let Some(top) = self.scenes.last_mut() {
+            top.resume(playdate);
+        }
+        let base = self.scenes.len();
+        self.start(playdate, transition, Some(leaving), base)
+    }
+
+    pub fn replace(&mut self, playdate: &mut Playdate, mut scene: Box<dyn Scene>,
+                   transition: Transition) -> Result<()> {
+        self.active = None;
+        let leaving = self.scenes.pop().map(|mut leaving| {
+            leaving.exit(playdate);
+            leaving
+        });
+        scene.enter(playdate);
+        let base = self.scenes.len();
+        self.scenes.push(scene);
+        self.start(playdate, transition, leaving, base)
+    }
+
+    fn start(&mut self, playdate: &mut Playdate, transition: Transition,
+             leaving: Option<Box<dyn Scene>>, base: usize) -> Result<()> {
+        if transition.duration() == 0 {
+            return Ok(());
+        }
+        let black = Color::SolidColor(SolidColor::kColorBlack);
+        self.active = Some(Active {
+            transition,
+            start: playdate.system().get_current_time_ms(),
+            leaving,
+            base,
+            from: Bitmap::new(COLUMNS, ROWS, black)?,
+            to: Bitmap::new(COLUMNS, ROWS, black)?,
+        });
+        Ok(())
+    }
+
+    pub fn update(&mut self, playdate: &mut Playdate) -> Result<()> {
+        if self.active.is_some() {
+            return self.draw_transition(playdate);
+        }
+        let action = match self.scenes.last_mut() {
+            Some(top) => top.update(playdate)?,
+            None => Action::None,
+        };
+        match action {
+            Action::None => {}
+            Action::Push(scene, transition) => self.push(playdate, scene, transition)?,
+            Action::Pop(transition) => self.pop(playdate, transition)?,
+            Action::Replace(scene, transition) => self.replace(playdate, scene, transition)?,
+        }
+        if self.active.is_some() {
+            return self.draw_transition(playdate);
+        }
+        draw_scenes(playdate, &mut self.scenes, None);
+        Ok(())
+    }
+
+    pub fn event(&mut self, playdate: &mut Playdate, event: SystemEvent, arg: u32) {
+        let top = match self.scenes.last_mut() {
+            Some(top) => top,
+            None => return,
+        };
+        match event {
+            SystemEvent::kEventPause => top.pause(playdate),
+            SystemEvent::kEventResume => top.resume(playdate),
+            _ => top.event(playdate, event, arg),
+        }
+    }
+
+    fn draw_transition(&mut self, playdate: &mut Playdate) -> Result<()> {
+        let active = match self.active.as_mut() {
+            Some(active) => active,
+            None => return Ok(()),
+        };
+        let graphics = playdate.graphics();
+        let elapsed = playdate.system().get_current_time_ms().wrapping_sub(active.start);
+        let duration = active.transition.duration();
+        let t = (elapsed as f32 / duration as f32).min(1.0);
+        {
+            let _target = graphics.push_context(&mut active.from);
+            let base = active.base.min(self.scenes.len());
+            draw_scenes(playdate, &mut self.scenes[..base], active.leaving.as_deref_mut());
+        }
+        {
+            let _target = graphics.push_context(&mut active.to);
+            draw_scenes(playdate, &mut self.scenes, None);
+        }
+        let _offset = graphics.push_draw_offset(0, 0);
+        let (w, h) = (COLUMNS as i32, ROWS as i32);
+        let flip = BitmapFlip::kBitmapUnflipped;
+        match active.transition {
+            Transition::None => active.to.draw(0, 0, flip),
+            Transition::Fade(_) => {
+                let (bitmap, level) = if t < 0.5 {
+                    (&active.from, t * 2.0)
+                } else {
+                    (&active.to, (1.0 - t) * 2.0)
+                };
+                bitmap.draw(0, 0, flip);
+                // Black where the Bayer pattern for `level` is set, clear
+                // elsewhere, so the scene darkens evenly.
+                let coverage = Pattern::bayer8((level * 64.0 + 0.5) as u8).bitmap();
+                let fade = Pattern::with_mask([0; 8], coverage);
+                graphics.fill_rect(0, 0, w, h, Color::Pattern(&fade));
+            }
+            Transition::Wipe(_, direction) => {
+                active.from.draw(0, 0, flip);
+                let (dx, dy) = ((t * w as f32) as i32, (t * h as f32) as i32);
+                let (x, y, width, height) = match direction {
+                    Direction::Left => (w - dx, 0, dx, h),
+                    Direction::Right => (0, 0, dx, h),
+                    Direction::Up => (0, h - dy, w, dy),
+                    Direction::Down => (0, 0, w, dy),
+                };
+                let _clip = graphics.push_clip_rect(x, y, width, height);
+                active.to.draw(0, 0, flip);
+            }
+            Transition::Slide(_, direction) => {
+                // The new scene follows one screen behind the old one.
+                let (sx, sy) = match direction {
+                    Direction::Left => (-w, 0),
+                    Direction::Right => (w, 0),
+                    Direction::Up => (0, -h),
+                    Direction::Down => (0, h),
+                };
+                let (dx, dy) = ((t * sx as f32) as i32, (t * sy as f32) as i32);
+                active.from.draw(dx, dy, flip);
+                active.to.draw(dx - sx, dy - sy, flip);
+            }
+        }
+        if t >= 1.0 {
+            self.active = None;
+        }
+        Ok(())
+    }
+}
+
+// Draws the top scene of `scenes` (or `top`, when given) and every overlay
+// directly beneath it, down to the first opaque scene.
+fn draw_scenes(playdate: &mut Playdate, scenes: &mut [Box<dyn Scene>],
+               top: Option<&mut (dyn Scene + 'static)>) {
+    let mut start = scenes.len();
+    let covered = top.as_ref().map_or(false, |top| !top.is_overlay());
+    if !covered {
+        while start > 0 {
+            start -= 1;
+            if !scenes[start].is_overlay() {
+                break;
+            }
+        }
+    }
+    for scene in &mut scenes[start..] {
+        scene.draw(playdate);
+    }
+    if let Some(top) = top {
+        top.draw(playdate);
+    }
+}
@@ -1,7 +1,8 @@
 use sys;
 use anyhow::{Result, ensure};
 use crate::Playdate;
-use crate::graphics::{Bitmap, BitmapFlip, BitmapDrawMode, Rect};
+use crate::graphics::{BitmapRef, BitmapFlip, BitmapDrawMode, BitmapTable, Rect};
+use core::marker::PhantomData;
 use core::ptr;
 
 pub use sys::PDRect as PDRect;
@@ -16,28 +17,43 @@ impl PDSprite {
     pub fn new(sprite: *const sys::playdate_sprite) -> Self {
         Self { sprite }
     }
+
+    pub fn add_sprite(&self, sprite: &Sprite) {
+        unsafe {
+            (*self.sprite).addSprite.unwrap()(sprite.sprite)
+        }
+    }
+
+    pub fn remove_sprite(&self, sprite: &Sprite) {
+        unsafe {
+            (*self.sprite).removeSprite.unwrap()(sprite.sprite)
+        }
+    }
 }
 
-pub struct Sprite {
+// The SDK keeps pointers to a sprite's image and stencil without owning
+// them, so a sprite borrows them for `'a`.
+pub struct Sprite<'a> {
     sprite: *mut sys::LCDSprite,
+    _marker: PhantomData<&'a ()>,
 }
 
-impl Sprite {
+impl<'a> Sprite<'a> {
     pub fn new() -> Result<Self> {
         unsafe {
             let spr = Playdate::get_sprite().sprite;
             let ptr = (*spr).newSprite.unwrap()();
             ensure!(!ptr.is_null(), "fail to create new sprite");
-            Ok(Self {sprite: ptr})
+            Ok(Self {sprite: ptr, _marker: PhantomData})
         }
     }
 
-    pub fn copy(sprite: &Sprite) -> Result<Self> {
+    pub fn copy(sprite: &Sprite<'a>) -> Result<Self> {
         unsafe {
             let spr = Playdate::get_sprite().sprite;
             let ptr = (*spr).copy.unwrap()(sprite.sprite);
             ensure!(!ptr.is_null(), "fail to copy new sprite");
-            Ok(Self {sprite: ptr})
+            Ok(Self {sprite: ptr, _marker: PhantomData})
         }
     }
 
@@ -79,19 +95,30 @@ impl Sprite {
         }
     }
 
-    pub fn set_image(&mut self, image: Bitmap, flip: BitmapFlip) {
+    pub fn set_image(&mut self, image: &'a BitmapRef<'_>, flip: BitmapFlip) {
         unsafe {
             let spr = Playdate::get_sprite().sprite;
             (*spr).setImage.unwrap()(self.sprite, image.bitmap, flip);
         }
     }
 
-    pub fn image(&mut self) -> Result<Bitmap> {
+    // Entry `index` of `table`, which owns the bitmap.
+    pub fn set_table_image(&mut self, table: &'a BitmapTable, index: usize,
+                           flip: BitmapFlip) -> Result<()> {
+        let image = table.get_bitmap(index)?;
+        unsafe {
+            let spr = Playdate::get_sprite().sprite;
+            (*spr).setImage.unwrap()(self.sprite, image.bitmap, flip);
+        }
+        Ok(())
+    }
+
+    pub fn image(&self) -> Result<BitmapRef<'_>> {
         unsafe {
             let spr = Playdate::get_sprite().sprite;
             let ptr = (*spr).getImage.unwrap()(self.sprite);
             ensure!(!ptr.is_null(), "failed to retrieve bitmap");
-            Ok(Bitmap { bitmap: ptr })
+            Ok(BitmapRef::from_raw(ptr))
         }
     }
 
@@ -102,6 +129,21 @@ impl Sprite {
         }
     }
 
+    // Relative to the sprite's bounds.
+    pub fn set_collide_rect(&mut self, collide_rect: PDRect) {
+        unsafe {
+            let spr = Playdate::get_sprite().sprite;
+            (*spr).setCollideRect.unwrap()(self.sprite, collide_rect)
+        }
+    }
+
+    pub fn collide_rect(&self) -> PDRect {
+        unsafe {
+            let spr = Playdate::get_sprite().sprite;
+            (*spr).getCollideRect.unwrap()(self.sprite)
+        }
+    }
+
     pub fn set_z_index(&mut self, z_index: i16) {
         unsafe {
             let spr = Playdate::get_sprite().sprite;
@@ -144,12 +186,12 @@ impl Sprite {
         }
     }
 
-     pub fn set_stencil(&mut self, stencil: Bitmap) {
+    pub fn set_stencil(&mut self, stencil: &'a BitmapRef<'_>) {
         unsafe {
             let spr = Playdate::get_sprite().sprite;
             (*spr).setStencil.unwrap()(self.sprite, stencil.bitmap)
         }
-     }
+    }
 
     pub fn set_clip_rect(&mut self, clip_rect: Rect) {
         unsafe {
@@ -222,7 +264,7 @@ impl Sprite {
     }
 }
 
-impl Drop for Sprite {
+impl Drop for Sprite<'_> {
     fn drop(&mut self) {
         unsafe {
             let spr = Playdate::get_sprite().sprite;
@@ -231,9 +273,9 @@ impl Drop for Sprite {
     }
 }
 
-pub struct CollisionInfo {
-    sprite: Sprite,
-    other: Sprite,
+pub struct CollisionInfo<'a> {
+    sprite: Sprite<'a>,
+    other: Sprite<'a>,
     response_type: CollisionResponseType,
     overlap: u8,
     ti: f32,
@@ -244,8 +286,8 @@ pub struct CollisionInfo {
     other_rect: PDRect,
 }
 
-pub struct QueryInfo {
-    sprite: Sprite,
+pub struct QueryInfo<'a> {
+    sprite: Sprite<'a>,
     ti1: f32,
     ti2: f32,
     entry_point: Vec2<f32>,
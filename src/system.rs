@@ -6,6 +6,7 @@ pub use sys::PDPeripherals as Peripherals;
 pub use sys::PDButtons as Buttons;
 pub use sys::PDCallbackFunction as CallbackFunction;
 pub use sys::PDLanguage as Language;
+pub use sys::PDSystemEvent as SystemEvent;
 
 #[derive(Copy, Clone)]
 pub struct System {
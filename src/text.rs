@@ -0,0 +1,271 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::graphics::{Font, Graphics, StringEncoding};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line<'t> {
+    pub text: &'t str,
+    pub ellipsis: bool,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+}
+
+// Lays text out in a box: paragraphs split on '\n', words wrapped to the
+// box width (or each paragraph kept to one line when wrapping is off),
+// and the last line that fits cut short with an ellipsis.
+pub struct TextLayout<'a> {
+    font: &'a Font,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    alignment: Alignment,
+    line_spacing: i32,
+    tracking: i32,
+    encoding: StringEncoding,
+    wrap: bool,
+    ellipsis: &'a str,
+}
+
+impl<'a> TextLayout<'a> {
+    pub fn new(font: &'a Font, x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            font,
+            x,
+            y,
+            width,
+            height,
+            alignment: Alignment::Left,
+            line_spacing: 0,
+            tracking: 0,
+            encoding: StringEncoding::kUTF8Encoding,
+            wrap: true,
+            ellipsis: "...",
+        }
+    }
+
+    pub fn alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn line_spacing(mut self, line_spacing: i32) -> Self {
+        self.line_spacing = line_spacing;
+        self
+    }
+
+    pub fn tracking(mut self, tracking: i32) -> Self {
+        self.tracking = tracking;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: StringEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn ellipsis(mut self, ellipsis: &'a str) -> Self {
+        self.ellipsis = ellipsis;
+        self
+    }
+
+    fn measure(&self, text: &str) -> i32 {
+        self.font.text_width_encoded(text, self.encoding, self.tracking)
+    }
+
+    pub fn layout<'t>(&self, text: &'t str) -> Vec<Line<'t>> {
+        let line_height = self.font.height() + self.line_spacing;
+        let max_lines = if line_height > 0 {
+            ((self.height + self.line_spacing) / line_height).max(0) as usize
+        } else {
+            usize::MAX
+        };
+        let measure = |text: &str| self.measure(text);
+        let (lines, truncated) = if self.wrap {
+            wrap(text, self.width, max_lines, measure)
+        } else {
+            single_lines(text, max_lines)
+        };
+        let last = lines.len().wrapping_sub(1);
+        lines.into_iter().enumerate().map(|(i, line)| {
+            let overflows = self.measure(line) > self.width;
+            let (text, ellipsis) = if overflows || (truncated && i == last) {
+                (fit_with_ellipsis(line, self.ellipsis, self.width, measure), true)
+            } else {
+                (line, false)
+            };
+            let width = if ellipsis {
+                self.measure(&[text, self.ellipsis].concat())
+            } else {
+                self.measure(text)
+            };
+            let x = match self.alignment {
+                Alignment::Left => self.x,
+                Alignment::Center => self.x + (self.width - width) / 2,
+                Alignment::Right => self.x + self.width - width,
+            };
+            Line { text, ellipsis, x, y: self.y + i as i32 * line_height, width }
+        }).collect()
+    }
+
+    pub fn draw<'t>(&self, graphics: &Graphics, text: &'t str) -> Vec<Line<'t>> {
+        graphics.set_font(*self.font);
+        graphics.set_text_tracking(self.tracking);
+        let lines = self.layout(text);
+        for line in lines.iter() {
+            if line.ellipsis {
+                graphics.draw_text(&[line.text, self.ellipsis].concat(), self.encoding,
+                                   line.x, line.y);
+            } else {
+                graphics.draw_text(line.text, self.encoding, line.x, line.y);
+            }
+        }
+        lines
+    }
+}
+
+// Greedy word wrap, breaking words that don't fit on a line of their own.
+// Returns at most `max_lines` lines and whether any text was left over.
+pub fn wrap<'t, F: Fn(&str) -> i32>(text: &'t str, width: i32, max_lines: usize,
+                                    measure: F) -> (Vec<&'t str>, bool) {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut rest = paragraph.trim_start_matches(' ');
+        loop {
+            if lines.len() == max_lines {
+                return (lines, true);
+            }
+            if measure(rest) <= width {
+                lines.push(rest.trim_end());
+                break;
+            }
+            let mut end = 0;
+            for (i, c) in rest.char_indices() {
+                if c == ' ' && i > 0 && measure(&rest[..i]) <= width {
+                    end = i;
+                } else if c == ' ' && end > 0 {
+                    break;
+                }
+            }
+            if end == 0 {
+                end = longest_prefix(rest, width, &measure);
+            }
+            lines.push(rest[..end].trim_end());
+            rest = rest[end..].trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+        }
+    }
+    (lines, false)
+}
+
+// The longest prefix of `line` that still fits once `ellipsis` is added,
+// down to nothing when even the ellipsis alone is too wide.
+fn fit_with_ellipsis<'t, F: Fn(&str) -> i32>(line: &'t str, ellipsis: &str, width: i32,
+                                             measure: F) -> &'t str {
+    let mut with_ellipsis = String::with_capacity(line.len() + ellipsis.len());
+    let mut end = line.len();
+    loop {
+        let prefix = line[..end].trim_end();
+        with_ellipsis.clear();
+        with_ellipsis.push_str(prefix);
+        with_ellipsis.push_str(ellipsis);
+        if end == 0 || measure(&with_ellipsis) <= width {
+            return prefix;
+        }
+        end = line[..end].char_indices().last().map_or(0, |(i, _)| i);
+    }
+}
+
+fn single_lines(text: &str, max_lines: usize) -> (Vec<&str>, bool) {
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    let truncated = lines.len() > max_lines;
+    lines.truncate(max_lines);
+    (lines, truncated)
+}
+
+// Length in bytes of the longest prefix of `text` that fits, always
+// taking at least one character so wrapping makes progress.
+fn longest_prefix<F: Fn(&str) -> i32>(text: &str, width: i32, measure: &F) -> usize {
+    let mut end = 0;
+    for (i, c) in text.char_indices() {
+        let next = i + c.len_utf8();
+        if end > 0 && measure(&text[..next]) > width {
+            break;
+        }
+        end = next;
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    // One unit per character, whatever its width in bytes.
+    fn chars(text: &str) -> i32 {
+        text.chars().count() as i32
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        assert_eq!(wrap("the quick brown fox", 10, usize::MAX, chars),
+                   (vec!["the quick", "brown fox"], false));
+        assert_eq!(wrap("  fits   ", 10, usize::MAX, chars), (vec!["fits"], false));
+    }
+
+    #[test]
+    fn breaks_words_longer_than_a_line() {
+        assert_eq!(wrap("a supercalifragilistic word", 8, usize::MAX, chars),
+                   (vec!["a", "supercal", "ifragili", "stic", "word"], false));
+        // Breaks fall on character boundaries.
+        assert_eq!(wrap("héllo", 2, usize::MAX, chars), (vec!["hé", "ll", "o"], false));
+    }
+
+    #[test]
+    fn keeps_explicit_newlines() {
+        assert_eq!(wrap("one\n\n two three", 20, usize::MAX, chars),
+                   (vec!["one", "", "two three"], false));
+        assert_eq!(single_lines("one\ntwo\nthree", 2), (vec!["one", "two"], true));
+        assert_eq!(single_lines("one\ntwo", 2), (vec!["one", "two"], false));
+    }
+
+    #[test]
+    fn stops_at_max_lines() {
+        assert_eq!(wrap("aa bb cc dd", 5, 2, chars), (vec!["aa bb", "cc dd"], false));
+        assert_eq!(wrap("aa bb cc dd", 5, 1, chars), (vec!["aa bb"], true));
+        assert_eq!(wrap("one\ntwo", 10, 1, chars), (vec!["one"], true));
+        assert_eq!(wrap("one", 10, 0, chars), (vec![], true));
+    }
+
+    #[test]
+    fn ellipsis_replaces_the_tail() {
+        assert_eq!(fit_with_ellipsis("aa bb", "...", 5, chars), "aa");
+        assert_eq!(fit_with_ellipsis("abcdef", "...", 5, chars), "ab");
+        assert_eq!(fit_with_ellipsis("ab", "...", 5, chars), "ab");
+        // Not even the ellipsis fits.
+        assert_eq!(fit_with_ellipsis("abc", "...", 2, chars), "");
+    }
+
+    #[test]
+    fn narrow_widths_still_make_progress() {
+        assert_eq!(wrap("ab cd", 0, usize::MAX, chars), (vec!["a", "b", "c", "d"], false));
+        assert_eq!(wrap("ab", -5, usize::MAX, chars), (vec!["a", "b"], false));
+        assert_eq!(wrap("", 0, usize::MAX, chars), (vec![""], false));
+    }
+}
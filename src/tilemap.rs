@@ -0,0 +1,641 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use anyhow::{Result, Error, ensure};
+use crate::Playdate;
+use crate::graphics::{BitmapFlip, BitmapTable, Color, SolidColor, COLUMNS, ROWS};
+use crate::json::{Value, ValueType, Visitor};
+use crate::sprite::{PDRect, Sprite};
+
+// Tiles are stored the way Tiled stores them: 0 is empty, otherwise the
+// low bits are the table index plus one and the top bits flip the tile.
+pub const FLIP_X: u32 = 0x8000_0000;
+pub const FLIP_Y: u32 = 0x4000_0000;
+// Tiled's diagonal flip (used for rotations) and hexagonal 120 degree
+// rotation, which have no `BitmapFlip`.
+const FLIP_UNSUPPORTED: u32 = 0x3000_0000;
+const INDEX_MASK: u32 = 0x0FFF_FFFF;
+
+pub const fn tile(index: u32) -> u32 {
+    index + 1
+}
+
+fn tile_index(tile: u32) -> Option<u32> {
+    match tile & INDEX_MASK {
+        0 => None,
+        n => Some(n - 1),
+    }
+}
+
+fn tile_flip(tile: u32) -> BitmapFlip {
+    match (tile & FLIP_X != 0, tile & FLIP_Y != 0) {
+        (false, false) => BitmapFlip::kBitmapUnflipped,
+        (true, false) => BitmapFlip::kBitmapFlippedX,
+        (false, true) => BitmapFlip::kBitmapFlippedY,
+        (true, true) => BitmapFlip::kBitmapFlippedXY,
+    }
+}
+
+pub struct TileLayer {
+    pub name: String,
+    pub visible: bool,
+    pub collision: bool,
+    tiles: Vec<u32>,
+}
+
+impl TileLayer {
+    pub fn tiles(&self) -> &[u32] {
+        &self.tiles
+    }
+}
+
+struct Animation {
+    frames: Vec<(u32, u32)>,
+    duration: u32,
+}
+
+impl Animation {
+    fn frame(&self, elapsed: u32) -> u32 {
+        let mut t = elapsed % self.duration.max(1);
+        for &(index, duration) in &self.frames {
+            if t < duration {
+                return index;
+            }
+            t -= duration;
+        }
+        self.frames[0].0
+    }
+}
+
+// A grid of tiles drawn from a `BitmapTable`, in one or more layers drawn
+// first to last. Only tiles inside the screen are drawn. Rows changed by
+// `set_tile` or an animation stepping are remembered so `draw_dirty` can
+// redraw just those.
+pub struct TileMap<'a> {
+    table: &'a BitmapTable,
+    tile_width: i32,
+    tile_height: i32,
+    width: usize,
+    height: usize,
+    layers: Vec<TileLayer>,
+    animations: BTreeMap<u32, Animation>,
+    elapsed: u32,
+    dirty: Vec<bool>,
+    background: SolidColor,
+}
+
+impl<'a> TileMap<'a> {
+    pub fn new(table: &'a BitmapTable, tile_width: i32, tile_height: i32,
+               width: usize, height: usize) -> Result<Self> {
+        ensure!(tile_width > 0 && tile_height > 0,
+                "tile size {}x{} is not positive", tile_width, tile_height);
+        Ok(Self {
+            table,
+            tile_width,
+            tile_height,
+            width,
+            height,
+            layers: Vec::new(),
+            animations: BTreeMap::new(),
+            elapsed: 0,
+            dirty: vec![false; height],
+            background: SolidColor::kColorWhite,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn tile_size(&self) -> (i32, i32) {
+        (self.tile_width, self.tile_height)
+    }
+
+    pub fn pixel_size(&self) -> (i32, i32) {
+        (self.width as i32 * self.tile_width, self.height as i32 * self.tile_height)
+    }
+
+    // The color `draw_dirty` clears a row to before redrawing it.
+    pub fn set_background(&mut self, color: SolidColor) {
+        self.background = color;
+    }
+
+    pub fn add_layer(&mut self, name: &str) -> usize {
+        self.layers.push(TileLayer {
+            name: String::from(name),
+            visible: true,
+            collision: false,
+            tiles: vec![0; self.width * self.height],
+        });
+        self.layers.len() - 1
+    }
+
+    pub fn layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    pub fn layer_mut(&mut self, layer: usize) -> Option<&mut TileLayer> {
+        self.mark_all_dirty();
+        self.layers.get_mut(layer)
+    }
+
+    pub fn find_layer(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    pub fn tile(&self, layer: usize, x: usize, y: usize) -> u32 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        self.layers.get(layer).map_or(0, |layer| layer.tiles[y * self.width + x])
+    }
+
+    pub fn set_tile(&mut self, layer: usize, x: usize, y: usize, tile: u32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.tiles[y * self.width + x] = tile;
+            self.dirty[y] = true;
+        }
+    }
+
+    // Every occurrence of table index `index` cycles through `frames` of
+    // (table index, milliseconds).
+    pub fn set_animation(&mut self, index: u32, frames: &[(u32, u32)]) {
+        if frames.is_empty() {
+            self.animations.remove(&index);
+        } else {
+            let duration = frames.iter().map(|&(_, duration)| duration).sum();
+            self.animations.insert(index, Animation { frames: frames.to_vec(), duration });
+        }
+        self.mark_tile_dirty(index);
+    }
+
+    pub fn update(&mut self, dt_ms: u32) {
+        let before = self.elapsed;
+        self.elapsed = self.elapsed.wrapping_add(dt_ms);
+        let changed: Vec<u32> = self.animations.iter()
+            .filter(|(_, animation)| animation.frame(before) != animation.frame(self.elapsed))
+            .map(|(&index, _)| index)
+            .collect();
+        for index in changed {
+            self.mark_tile_dirty(index);
+        }
+    }
+
+    fn mark_tile_dirty(&mut self, index: u32) {
+        let width = self.width.max(1);
+        for layer in &self.layers {
+            for (i, &tile) in layer.tiles.iter().enumerate() {
+                if tile_index(tile) == Some(index) {
+                    self.dirty[i / width] = true;
+                }
+            }
+        }
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        for row in self.dirty.iter_mut() {
+            *row = true;
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.iter().any(|&row| row)
+    }
+
+    // Draws the visible part of every visible layer with the map's
+    // top-left corner at (-x, -y) on screen.
+    pub fn draw(&mut self, x: i32, y: i32) {
+        let (first, last) = self.visible_rows(y);
+        for row in first..last {
+            self.draw_row(row, x, y);
+        }
+        for row in self.dirty.iter_mut() {
+            *row = false;
+        }
+    }
+
+    // Clears and redraws only the visible rows that changed since the last
+    // draw, leaving the rest of the frame alone.
+    pub fn draw_dirty(&mut self, x: i32, y: i32) {
+        let graphics = Playdate::get_graphics();
+        let (first, last) = self.visible_rows(y);
+        for row in first..last {
+            if self.dirty[row] {
+                graphics.fill_rect(0, row as i32 * self.tile_height - y, COLUMNS as i32,
+                                   self.tile_height, Color::SolidColor(self.background));
+                self.draw_row(row, x, y);
+            }
+        }
+        for row in self.dirty.iter_mut() {
+            *row = false;
+        }
+    }
+
+    fn visible_rows(&self, y: i32) -> (usize, usize) {
+        let first = y.div_euclid(self.tile_height).max(0) as usize;
+        let last = (y + ROWS as i32 + self.tile_height - 1).div_euclid(self.tile_height);
+        (first.min(self.height), (last.max(0) as usize).min(self.height))
+    }
+
+    fn draw_row(&self, row: usize, x: i32, y: i32) {
+        let first = x.div_euclid(self.tile_width).max(0) as usize;
+        let last = (x + COLUMNS as i32 + self.tile_width - 1).div_euclid(self.tile_width);
+        let last = (last.max(0) as usize).min(self.width);
+        let sy = row as i32 * self.tile_height - y;
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            for col in first..last {
+                let tile = layer.tiles[row * self.width + col];
+                let index = match tile_index(tile) {
+                    Some(index) => index,
+                    None => continue,
+                };
+                let index = self.animations.get(&index)
+                    .map_or(index, |animation| animation.frame(self.elapsed));
+                if let Ok(bitmap) = self.table.get_bitmap(index as usize) {
+                    bitmap.draw(col as i32 * self.tile_width - x, sy, tile_flip(tile));
+                }
+            }
+        }
+    }
+
+    // The solid tiles of `layer` as map-space pixel rectangles: runs of
+    // tiles in a row are joined, then identical runs in consecutive rows.
+    pub fn collision_rects(&self, layer: usize) -> Vec<PDRect> {
+        let layer = match self.layers.get(layer) {
+            Some(layer) => layer,
+            None => return Vec::new(),
+        };
+        // (first column, end column, first row, end row) of each open run.
+        let mut open: Vec<(usize, usize, usize, usize)> = Vec::new();
+        let mut closed = Vec::new();
+        for row in 0..self.height {
+            let mut runs = Vec::new();
+            let mut col = 0;
+            while col < self.width {
+                if layer.tiles[row * self.width + col] & INDEX_MASK == 0 {
+                    col += 1;
+                    continue;
+                }
+                let start = col;
+                while col < self.width && layer.tiles[row * self.width + col] & INDEX_MASK != 0 {
+                    col += 1;
+                }
+                runs.push((start, col));
+            }
+            let mut next = Vec::with_capacity(runs.len());
+            for (start, end) in runs {
+                match open.iter().position(|r| r.0 == start && r.1 == end) {
+                    Some(i) => {
+                        let mut run = open.swap_remove(i);
+                        run.3 = row + 1;
+                        next.push(run);
+                    }
+                    None => next.push((start, end, row, row + 1)),
+                }
+            }
+            closed.append(&mut open);
+            open = next;
+        }
+        closed.append(&mut open);
+        closed.into_iter().map(|(x0, x1, y0, y1)| PDRect {
+            x: (x0 as i32 * self.tile_width) as f32,
+            y: (y0 as i32 * self.tile_height) as f32,
+            width: ((x1 - x0) as i32 * self.tile_width) as f32,
+            height: ((y1 - y0) as i32 * self.tile_height) as f32,
+        }).collect()
+    }
+
+    // Adds an invisible sprite per collision rect of `layer` to the display
+    // list, so other sprites collide with the level. The sprites are
+    // removed and freed when the returned list is dropped.
+    pub fn add_collision_sprites(&self, layer: usize) -> Result<Vec<Sprite<'static>>> {
+        let mut sprites = Vec::new();
+        for rect in self.collision_rects(layer) {
+            let mut sprite = Sprite::new()?;
+            sprite.set_bounds(rect);
+            sprite.set_collide_rect(PDRect { x: 0.0, y: 0.0, width: rect.width, height: rect.height });
+            sprite.set_visible(false);
+            Playdate::get_sprite().add_sprite(&sprite);
+            sprites.push(sprite);
+        }
+        Ok(sprites)
+    }
+
+    // Loads a map exported from Tiled as JSON, drawing its tiles from
+    // `table`, which must hold the map's tilesets back to back in order,
+    // each starting at its `firstgid`. Layer data may be CSV or
+    // uncompressed Base64; Base64 keeps flipped tile ids exact. Top-level
+    // and grouped tile layers are read with their `visible` flag; a layer
+    // with a boolean `collision` property is marked as a collision layer.
+    // Tile animations from the tileset are applied.
+    pub fn load_tiled(path: &str, table: &'a BitmapTable) -> Result<Self> {
+        let mut visitor = TiledVisitor::default();
+        Playdate::get_json().decode_file(path, &mut visitor)?;
+        if let Some(error) = visitor.error {
+            return Err(Error::msg(error));
+        }
+        let (width, height) = (visitor.width.max(0) as usize, visitor.height.max(0) as usize);
+        ensure!(width > 0 && height > 0, "Tiled map {} has no size", path);
+        let first_gid = visitor.first_gids.first().copied().flatten().unwrap_or(1);
+        let mut map = Self::new(table, visitor.tile_width, visitor.tile_height, width, height)?;
+        for mut layer in visitor.layers {
+            if layer.tiles.len() != width * height {
+                continue;
+            }
+            for raw in layer.tiles.iter_mut() {
+                ensure!(*raw & FLIP_UNSUPPORTED == 0,
+                        "Tiled layer {} has rotated or diagonally flipped tiles", layer.name);
+                *raw = from_gid(*raw, first_gid);
+            }
+            map.layers.push(layer);
+        }
+        // Animation tile ids are local to their tileset.
+        for (tileset, id, frames) in visitor.animations {
+            let start = visitor.first_gids[tileset].unwrap_or(first_gid).saturating_sub(first_gid);
+            let frames: Vec<(u32, u32)> = frames.iter()
+                .map(|&(index, duration)| (start + index, duration))
+                .collect();
+            map.set_animation(start + id, &frames);
+        }
+        map.mark_all_dirty();
+        Ok(map)
+    }
+}
+
+// The map's tile for a Tiled global tile id, given the first tileset's
+// `firstgid`. Ids below it are empty.
+fn from_gid(raw: u32, first_gid: u32) -> u32 {
+    let gid = raw & INDEX_MASK;
+    if gid < first_gid {
+        0
+    } else {
+        (raw & (FLIP_X | FLIP_Y)) | tile(gid - first_gid)
+    }
+}
+
+// Little-endian tile ids packed in standard Base64.
+fn decode_base64(text: &str) -> Option<Vec<u32>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => continue,
+            _ => return None,
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    if bytes.len() % 4 != 0 {
+        return None;
+    }
+    Some(bytes.chunks(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+}
+
+struct LayerBuilder {
+    name: String,
+    kind: String,
+    visible: bool,
+    collision: bool,
+    tiles: Vec<u32>,
+    data: Option<String>,
+    encoding: String,
+    compression: String,
+}
+
+// Tracks where the SAX-style decoder is by the key each open container was
+// found under ("" for array elements), matching on the innermost keys.
+#[derive(Default)]
+struct TiledVisitor {
+    stack: Vec<String>,
+    key: String,
+    width: i32,
+    height: i32,
+    tile_width: i32,
+    tile_height: i32,
+    // Each tileset's `firstgid`, in order.
+    first_gids: Vec<Option<u32>>,
+    layers: Vec<TileLayer>,
+    layer: Option<LayerBuilder>,
+    property: (String, bool),
+    tile_id: Option<u32>,
+    frames: Vec<(u32, u32)>,
+    frame: (u32, u32),
+    // (tileset, tile id, frames), all ids local to the tileset.
+    animations: Vec<(usize, u32, Vec<(u32, u32)>)>,
+    error: Option<String>,
+}
+
+impl TiledVisitor {
+    fn at(&self, suffix: &[&str]) -> bool {
+        self.stack.len() >= suffix.len()
+            && self.stack[self.stack.len() - suffix.len()..].iter().zip(suffix).all(|(a, b)| a == b)
+    }
+
+    fn at_root(&self) -> bool {
+        self.stack.len() <= 1
+    }
+}
+
+fn int(value: Value) -> Option<i32> {
+    match value {
+        Value::Integer(n) => Some(n),
+        Value::Float(f) => Some(f as i32),
+        _ => None,
+    }
+}
+
+impl Visitor for TiledVisitor {
+    fn will_decode_sublist(&mut self, _name: &str, kind: ValueType) {
+        let key = core::mem::take(&mut self.key);
+        self.stack.push(key);
+        if kind == ValueType::kJSONTable && self.at(&["layers", ""]) {
+            self.layer = Some(LayerBuilder {
+                name: String::new(),
+                kind: String::new(),
+                visible: true,
+                collision: false,
+                tiles: Vec::new(),
+                data: None,
+                encoding: String::new(),
+                compression: String::new(),
+            });
+        } else if kind == ValueType::kJSONTable && self.at(&["tilesets", ""]) {
+            self.first_gids.push(None);
+        } else if self.at(&["tiles", ""]) {
+            self.tile_id = None;
+            self.frames.clear();
+        } else if self.at(&["properties", ""]) {
+            self.property = (String::new(), false);
+        }
+    }
+
+    fn should_decode_table_value(&mut self, key: &str) -> bool {
+        self.key = String::from(key);
+        true
+    }
+
+    fn table_value(&mut self, key: &str, value: Value) {
+        if self.at_root() {
+            let n = int(value).unwrap_or(0);
+            match key {
+                "width" => self.width = n,
+                "height" => self.height = n,
+                "tilewidth" => self.tile_width = n,
+                "tileheight" => self.tile_height = n,
+                "infinite" if value == Value::Bool(true) => {
+                    self.error = Some(String::from("infinite Tiled maps are not supported"));
+                }
+                _ => {}
+            }
+        } else if self.at(&["layers", ""]) {
+            if let Some(layer) = self.layer.as_mut() {
+                match (key, value) {
+                    ("name", Value::String(name)) => layer.name = String::from(name),
+                    ("type", Value::String(kind)) => layer.kind = String::from(kind),
+                    ("visible", Value::Bool(visible)) => layer.visible = visible,
+                    ("data", Value::String(data)) => layer.data = Some(String::from(data)),
+                    ("encoding", Value::String(encoding)) => {
+                        layer.encoding = String::from(encoding)
+                    }
+                    ("compression", Value::String(compression)) => {
+                        layer.compression = String::from(compression)
+                    }
+                    _ => {}
+                }
+            }
+        } else if self.at(&["layers", "", "properties", ""]) {
+            match (key, value) {
+                ("name", Value::String(name)) => self.property.0 = String::from(name),
+                ("value", Value::Bool(value)) => self.property.1 = value,
+                _ => {}
+            }
+        } else if self.at(&["tilesets", ""]) {
+            if let (true, Some(first_gid)) = (key == "firstgid", self.first_gids.last_mut()) {
+                *first_gid = int(value).map(|n| n as u32);
+            }
+        } else if self.at(&["tilesets", "", "tiles", ""]) {
+            if key == "id" {
+                self.tile_id = int(value).map(|n| n as u32);
+            }
+        } else if self.at(&["tiles", "", "animation", ""]) {
+            match key {
+                "tileid" => self.frame.0 = int(value).unwrap_or(0) as u32,
+                "duration" => self.frame.1 = int(value).unwrap_or(0) as u32,
+                _ => {}
+            }
+        }
+    }
+
+    fn should_decode_array_value(&mut self, _pos: i32) -> bool {
+        self.key.clear();
+        true
+    }
+
+    fn array_value(&mut self, _pos: i32, value: Value) {
+        if self.at(&["layers", "", "data"]) {
+            if let Some(layer) = self.layer.as_mut() {
+                match value {
+                    // Flipped tile ids don't fit in an i32, so they may
+                    // arrive wrapped, or as a float missing its low bits.
+                    Value::Integer(n) => layer.tiles.push(n as u32),
+                    Value::Float(_) => {
+                        self.error = Some(String::from(
+                            "Tiled tile ids above 2^31 lost precision; export the layer as \
+                             uncompressed Base64"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn did_decode_sublist(&mut self, _name: &str, _kind: ValueType) {
+        if self.at(&["layers", ""]) {
+            if let Some(mut layer) = self.layer.take() {
+                if let Some(data) = layer.data.take() {
+                    if layer.encoding != "base64" || !layer.compression.is_empty() {
+                        self.error = Some(String::from(
+                            "Tiled layer data must be CSV or uncompressed Base64"));
+                    } else if let Some(tiles) = decode_base64(&data) {
+                        layer.tiles = tiles;
+                    } else {
+                        self.error = Some(String::from("bad Base64 in Tiled layer data"));
+                    }
+                }
+                if layer.kind == "tilelayer" {
+                    self.layers.push(TileLayer {
+                        name: layer.name,
+                        visible: layer.visible,
+                        collision: layer.collision,
+                        tiles: layer.tiles,
+                    });
+                }
+            }
+        } else if self.at(&["layers", "", "properties", ""]) {
+            if self.property.0 == "collision" && self.property.1 {
+                if let Some(layer) = self.layer.as_mut() {
+                    layer.collision = true;
+                }
+            }
+        } else if self.at(&["animation", ""]) {
+            self.frames.push(self.frame);
+            self.frame = (0, 0);
+        } else if self.at(&["tilesets", "", "tiles", ""]) {
+            if let Some(id) = self.tile_id {
+                if !self.frames.is_empty() {
+                    let tileset = self.first_gids.len().saturating_sub(1);
+                    self.animations.push((tileset, id, core::mem::take(&mut self.frames)));
+                }
+            }
+        }
+        self.stack.pop();
+    }
+
+    fn decode_error(&mut self, error: &str, line: i32) {
+        self.error = Some(alloc::format!("{} at line {}", error, line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gids_keep_flips_and_drop_other_tilesets() {
+        assert_eq!(from_gid(0, 1), 0);
+        assert_eq!(from_gid(1, 1), tile(0));
+        assert_eq!(from_gid(10, 5), tile(5));
+        assert_eq!(from_gid(3, 5), 0);
+        assert_eq!(from_gid(FLIP_X | FLIP_Y | 7, 1), FLIP_X | FLIP_Y | tile(6));
+        assert_eq!(tile_flip(from_gid(FLIP_Y | 2, 1)), BitmapFlip::kBitmapFlippedY);
+        assert_eq!(tile_index(from_gid(FLIP_X | 2, 1)), Some(1));
+    }
+
+    #[test]
+    fn decodes_base64_layer_data() {
+        // 1, 0x80000002 (flipped in X) and 0x40000003 (flipped in Y).
+        let tiles = decode_base64("AQAAAAIAAIADAABA").unwrap();
+        assert_eq!(tiles, [1, FLIP_X | 2, FLIP_Y | 3]);
+        assert_eq!(decode_base64(" AQAA\nAA== ").unwrap(), [1]);
+        assert_eq!(decode_base64("").unwrap(), []);
+        assert_eq!(decode_base64("AQA="), None);
+        assert_eq!(decode_base64("AQAA*A=="), None);
+    }
+}
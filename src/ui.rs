@@ -0,0 +1,1169 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use anyhow::{Result, ensure};
+use crate::graphics::{Bitmap, BitmapDrawMode, BitmapFlip, BitmapRef, Color, Font, Graphics,
+                      Pattern, Rect, SolidColor, StringEncoding, COLUMNS, ROWS};
+use crate::system::{Buttons, System};
+use crate::text::{Alignment, TextLayout};
+
+const BLACK: Color = Color::SolidColor(SolidColor::kColorBlack);
+const WHITE: Color = Color::SolidColor(SolidColor::kColorWhite);
+
+// One frame of button and crank input. Poll it once per update and pass
+// it to every widget that should react.
+#[derive(Copy, Clone)]
+pub struct Input {
+    pub current: Buttons,
+    pub pushed: Buttons,
+    pub released: Buttons,
+    pub crank_change: f32,
+    pub crank_docked: bool,
+}
+
+impl Input {
+    pub fn poll(system: &System) -> Self {
+        let (current, pushed, released) = system.get_button_state();
+        let crank_docked = system.is_crank_docked();
+        let crank_change = if crank_docked { 0.0 } else { system.get_crank_change() };
+        Self { current, pushed, released, crank_change, crank_docked }
+    }
+
+    pub fn is_pushed(&self, button: Buttons) -> bool {
+        self.pushed & button != Buttons(0)
+    }
+
+    pub fn is_held(&self, button: Buttons) -> bool {
+        self.current & button != Buttons(0)
+    }
+
+    pub fn is_released(&self, button: Buttons) -> bool {
+        self.released & button != Buttons(0)
+    }
+}
+
+// Turns crank motion into whole steps, like Lua's `getCrankTicks`. The
+// remainder carries over, so slow turns still add up to a step.
+#[derive(Copy, Clone, Debug)]
+pub struct CrankTicks {
+    degrees_per_tick: f32,
+    accumulated: f32,
+}
+
+impl CrankTicks {
+    pub fn new(ticks_per_revolution: u32) -> Self {
+        Self { degrees_per_tick: 360.0 / ticks_per_revolution.max(1) as f32, accumulated: 0.0 }
+    }
+
+    pub fn update(&mut self, change: f32) -> i32 {
+        self.accumulated += change;
+        let ticks = (self.accumulated / self.degrees_per_tick) as i32;
+        self.accumulated -= ticks as f32 * self.degrees_per_tick;
+        ticks
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulated = 0.0;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub row: usize,
+    pub column: usize,
+    pub selected: bool,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+// A scrolling grid of equally sized cells, like Lua's
+// `playdate.ui.gridview`. The grid only tracks selection and scrolling;
+// cells are drawn by the callback passed to `draw`. A cell width of 0
+// fills the view, which makes a one-column grid a list.
+pub struct GridView {
+    rows: usize,
+    columns: usize,
+    cell_width: i32,
+    cell_height: i32,
+    spacing: (i32, i32),
+    selection: (usize, usize),
+    wrap: bool,
+    scroll: (f32, f32),
+    target: (f32, f32),
+    scroll_lerp: f32,
+    follow_selection: bool,
+    crank: CrankTicks,
+    needs_display: bool,
+}
+
+impl GridView {
+    pub fn new(rows: usize, columns: usize, cell_width: i32, cell_height: i32) -> Self {
+        Self {
+            rows,
+            columns: columns.max(1),
+            cell_width,
+            cell_height,
+            spacing: (0, 0),
+            selection: (0, 0),
+            wrap: false,
+            scroll: (0.0, 0.0),
+            target: (0.0, 0.0),
+            scroll_lerp: 0.4,
+            follow_selection: true,
+            crank: CrankTicks::new(6),
+            needs_display: true,
+        }
+    }
+
+    pub fn list(rows: usize, cell_height: i32) -> Self {
+        Self::new(rows, 1, 0, cell_height)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn set_size(&mut self, rows: usize, columns: usize) {
+        self.rows = rows;
+        self.columns = columns.max(1);
+        let (row, column) = self.selection;
+        self.set_selection(row, column);
+    }
+
+    pub fn set_spacing(&mut self, horizontal: i32, vertical: i32) {
+        self.spacing = (horizontal, vertical);
+        self.needs_display = true;
+    }
+
+    pub fn set_wrap_selection(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    // Fraction of the remaining distance scrolled each frame; 1.0 jumps.
+    pub fn set_scroll_lerp(&mut self, lerp: f32) {
+        self.scroll_lerp = lerp.max(0.01).min(1.0);
+    }
+
+    pub fn set_crank_ticks(&mut self, ticks_per_revolution: u32) {
+        self.crank = CrankTicks::new(ticks_per_revolution);
+    }
+
+    pub fn selection(&self) -> (usize, usize) {
+        self.selection
+    }
+
+    pub fn set_selection(&mut self, row: usize, column: usize) {
+        let row = row.min(self.rows.saturating_sub(1));
+        let column = column.min(self.columns - 1);
+        if (row, column) != self.selection {
+            self.needs_display = true;
+        }
+        self.selection = (row, column);
+        self.follow_selection = true;
+    }
+
+    pub fn select_next_row(&mut self) {
+        let (row, column) = self.selection;
+        self.set_selection(step(row, 1, self.rows, self.wrap), column);
+    }
+
+    pub fn select_previous_row(&mut self) {
+        let (row, column) = self.selection;
+        self.set_selection(step(row, -1, self.rows, self.wrap), column);
+    }
+
+    pub fn select_next_column(&mut self) {
+        let (row, column) = self.selection;
+        self.set_selection(row, step(column, 1, self.columns, self.wrap));
+    }
+
+    pub fn select_previous_column(&mut self) {
+        let (row, column) = self.selection;
+        self.set_selection(row, step(column, -1, self.columns, self.wrap));
+    }
+
+    pub fn scroll_position(&self) -> (i32, i32) {
+        (self.scroll.0 as i32, self.scroll.1 as i32)
+    }
+
+    // Scrolls without moving the selection; it stops being followed until
+    // it next changes.
+    pub fn scroll_to(&mut self, x: i32, y: i32) {
+        self.target = (x as f32, y as f32);
+        self.follow_selection = false;
+        self.needs_display = true;
+    }
+
+    // True while scrolling or after the selection changed.
+    pub fn needs_display(&self) -> bool {
+        self.needs_display
+    }
+
+    // The d-pad moves the selection; the crank moves it by rows. Returns
+    // whether the selection changed.
+    pub fn handle_input(&mut self, input: &Input) -> bool {
+        let before = self.selection;
+        if input.is_pushed(Buttons::kButtonUp) {
+            self.select_previous_row();
+        }
+        if input.is_pushed(Buttons::kButtonDown) {
+            self.select_next_row();
+        }
+        if input.is_pushed(Buttons::kButtonLeft) {
+            self.select_previous_column();
+        }
+        if input.is_pushed(Buttons::kButtonRight) {
+            self.select_next_column();
+        }
+        let ticks = self.crank.update(input.crank_change);
+        for _ in 0..ticks.abs() {
+            if ticks > 0 {
+                self.select_next_row();
+            } else {
+                self.select_previous_row();
+            }
+        }
+        self.selection != before
+    }
+
+    fn cell_size(&self, view_width: i32) -> (i32, i32) {
+        let width = if self.cell_width > 0 { self.cell_width } else { view_width };
+        (width, self.cell_height)
+    }
+
+    pub fn content_size(&self, view_width: i32) -> (i32, i32) {
+        let (width, height) = self.cell_size(view_width);
+        let columns = self.columns as i32;
+        let rows = self.rows as i32;
+        (columns * width + (columns - 1).max(0) * self.spacing.0,
+         rows * height + (rows - 1).max(0) * self.spacing.1)
+    }
+
+    // Moves the scroll position a step toward its target, which follows the
+    // selection unless `scroll_to` was used, kept inside the content.
+    fn update_scroll(&mut self, width: i32, height: i32) {
+        let (cell_width, cell_height) = self.cell_size(width);
+        let (pitch_x, pitch_y) = (cell_width + self.spacing.0, cell_height + self.spacing.1);
+        let (content_width, content_height) = self.content_size(width);
+        if self.follow_selection {
+            let (row, column) = self.selection;
+            let (left, top) = (column as i32 * pitch_x, row as i32 * pitch_y);
+            let mut target = (self.target.0 as i32, self.target.1 as i32);
+            target.0 = target.0.min(left).max(left + cell_width - width);
+            target.1 = target.1.min(top).max(top + cell_height - height);
+            self.target = (target.0 as f32, target.1 as f32);
+        }
+        self.target.0 = self.target.0.min((content_width - width) as f32).max(0.0);
+        self.target.1 = self.target.1.min((content_height - height) as f32).max(0.0);
+        self.scroll.0 += (self.target.0 - self.scroll.0) * self.scroll_lerp;
+        self.scroll.1 += (self.target.1 - self.scroll.1) * self.scroll_lerp;
+        let settled = (self.target.0 - self.scroll.0).abs() < 0.5
+            && (self.target.1 - self.scroll.1).abs() < 0.5;
+        if settled {
+            self.scroll = self.target;
+        }
+        self.needs_display = !settled;
+    }
+
+    // Advances scrolling and calls `draw_cell` for each visible cell,
+    // clipped to the view. Cell positions are in screen coordinates.
+    pub fn draw<F: FnMut(&Graphics, &Cell)>(&mut self, graphics: &Graphics, x: i32, y: i32,
+                                            width: i32, height: i32, mut draw_cell: F) {
+        self.update_scroll(width, height);
+        let (cell_width, cell_height) = self.cell_size(width);
+        let (pitch_x, pitch_y) = (cell_width + self.spacing.0, cell_height + self.spacing.1);
+        if pitch_x <= 0 || pitch_y <= 0 {
+            return;
+        }
+        let (sx, sy) = self.scroll_position();
+        let _clip = graphics.push_clip_rect(x, y, width, height);
+        let first_row = (sy / pitch_y).max(0) as usize;
+        let last_row = ((sy + height) / pitch_y + 1).max(0) as usize;
+        let first_column = (sx / pitch_x).max(0) as usize;
+        let last_column = ((sx + width) / pitch_x + 1).max(0) as usize;
+        for row in first_row..last_row.min(self.rows) {
+            for column in first_column..last_column.min(self.columns) {
+                draw_cell(graphics, &Cell {
+                    row,
+                    column,
+                    selected: (row, column) == self.selection,
+                    x: x + column as i32 * pitch_x - sx,
+                    y: y + row as i32 * pitch_y - sy,
+                    width: cell_width,
+                    height: cell_height,
+                });
+            }
+        }
+    }
+}
+
+fn step(index: usize, delta: i32, count: usize, wrap: bool) -> usize {
+    if count == 0 {
+        return 0;
+    }
+    let next = index as i32 + delta;
+    if wrap {
+        next.rem_euclid(count as i32) as usize
+    } else {
+        next.max(0).min(count as i32 - 1) as usize
+    }
+}
+
+// Draws `text` centered in a cell, inverted when the cell is selected.
+pub fn draw_cell_text(graphics: &Graphics, font: &Font, cell: &Cell, text: &str) {
+    let mode = if cell.selected {
+        graphics.fill_rect(cell.x, cell.y, cell.width, cell.height, BLACK);
+        BitmapDrawMode::kDrawModeFillWhite
+    } else {
+        BitmapDrawMode::kDrawModeCopy
+    };
+    let _mode = graphics.push_draw_mode(mode);
+    TextLayout::new(font, cell.x + 2, cell.y + (cell.height - font.height()) / 2,
+                    cell.width - 4, font.height())
+        .alignment(Alignment::Center)
+        .wrap(false)
+        .draw(graphics, text);
+}
+
+// A push button. While focused it is drawn inverted and A presses it; it
+// activates when A is released, so holding A shows it pressed.
+pub struct Button {
+    label: String,
+    focused: bool,
+    pressed: bool,
+}
+
+impl Button {
+    pub fn new(label: &str) -> Self {
+        Self { label: String::from(label), focused: false, pressed: false }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn set_label(&mut self, label: &str) {
+        self.label = String::from(label);
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    // Losing focus cancels a press in progress.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.pressed &= focused;
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    // Returns true on the frame the button activates.
+    pub fn handle_input(&mut self, input: &Input) -> bool {
+        if !self.focused {
+            return false;
+        }
+        if input.is_pushed(Buttons::kButtonA) {
+            self.pressed = true;
+        }
+        if self.pressed && input.is_released(Buttons::kButtonA) {
+            self.pressed = false;
+            return true;
+        }
+        false
+    }
+
+    pub fn draw(&self, graphics: &Graphics, font: &Font, x: i32, y: i32, width: i32, height: i32) {
+        let cell = Cell { row: 0, column: 0, selected: self.focused, x, y, width, height };
+        graphics.draw_rect(x, y, width, height, BLACK);
+        draw_cell_text(graphics, font, &cell, &self.label);
+        if self.pressed {
+            graphics.draw_rect(x + 1, y + 1, width - 2, height - 2, WHITE);
+        }
+    }
+}
+
+// A horizontal slider over `min..=max` in steps of `step`. While focused,
+// left and right move it a step and the crank a step per tick.
+pub struct Slider {
+    min: i32,
+    max: i32,
+    step: i32,
+    value: i32,
+    focused: bool,
+    crank: CrankTicks,
+}
+
+impl Slider {
+    pub fn new(min: i32, max: i32, value: i32) -> Self {
+        let mut slider = Self {
+            min,
+            max: max.max(min),
+            step: 1,
+            value: min,
+            focused: false,
+            crank: CrankTicks::new(12),
+        };
+        slider.set_value(value);
+        slider
+    }
+
+    pub fn with_step(mut self, step: i32) -> Self {
+        self.step = step.max(1);
+        let value = self.value;
+        self.set_value(value);
+        self
+    }
+
+    pub fn set_crank_ticks(&mut self, ticks_per_revolution: u32) {
+        self.crank = CrankTicks::new(ticks_per_revolution);
+    }
+
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    // Clamped to the range and rounded to the nearest step from `min`.
+    pub fn set_value(&mut self, value: i32) {
+        let offset = value.max(self.min).min(self.max) - self.min;
+        let value = self.min + (offset + self.step / 2) / self.step * self.step;
+        self.value = if value > self.max { value - self.step } else { value };
+    }
+
+    // Position of the value in the range, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        if self.max == self.min {
+            0.0
+        } else {
+            (self.value - self.min) as f32 / (self.max - self.min) as f32
+        }
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.crank.reset();
+    }
+
+    // Returns whether the value changed.
+    pub fn handle_input(&mut self, input: &Input) -> bool {
+        if !self.focused {
+            return false;
+        }
+        let mut steps = self.crank.update(input.crank_change);
+        if input.is_pushed(Buttons::kButtonLeft) {
+            steps -= 1;
+        }
+        if input.is_pushed(Buttons::kButtonRight) {
+            steps += 1;
+        }
+        let before = self.value;
+        self.set_value(before.saturating_add(steps.saturating_mul(self.step)));
+        self.value != before
+    }
+
+    // Left edge of the knob, a `height`-wide circle, in a slider `width`
+    // wide.
+    fn knob_x(&self, width: i32, height: i32) -> i32 {
+        let travel = (width - height).max(0);
+        (self.fraction() * travel as f32 + 0.5) as i32
+    }
+
+    // A track across the middle, thick up to the knob, which is filled
+    // while focused.
+    pub fn draw(&self, graphics: &Graphics, x: i32, y: i32, width: i32, height: i32) {
+        let knob = x + self.knob_x(width, height);
+        let (left, right, middle) = (x + height / 2, x + width - height / 2, y + height / 2);
+        graphics.draw_line(left, middle, right, middle, 1, BLACK);
+        graphics.draw_line(left, middle, knob + height / 2, middle, 3, BLACK);
+        graphics.fill_ellipse(knob, y, height, height, 0.0, 360.0, WHITE);
+        if self.focused {
+            graphics.fill_ellipse(knob, y, height, height, 0.0, 360.0, BLACK);
+        } else {
+            graphics.draw_ellipse(knob, y, height, height, 2, 0.0, 360.0, BLACK);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fill {
+    Tile,
+    Stretch,
+}
+
+// A box drawn from a bitmap cut into nine pieces by `inset`, the rect of
+// its center, like Lua's `playdate.graphics.nineSlice`: corners are drawn
+// as they are and the edges and center tile (the default) or stretch to
+// fill. Boxes smaller than `min_size` are clipped rather than squashed.
+pub struct NineSlice {
+    pieces: Vec<Option<Bitmap>>,
+    size: (i32, i32),
+    left: i32,
+    right: i32,
+    top: i32,
+    bottom: i32,
+    edges: Fill,
+    center: Fill,
+}
+
+impl NineSlice {
+    pub fn new(bitmap: &BitmapRef, inset: Rect) -> Result<Self> {
+        let data = bitmap.data();
+        let (width, height) = (data.width as i32, data.height as i32);
+        ensure!(0 <= inset.left && inset.left <= inset.right && inset.right <= width
+                && 0 <= inset.top && inset.top <= inset.bottom && inset.bottom <= height,
+                "nine-slice inset is outside the bitmap");
+        let xs = [0, inset.left, inset.right, width];
+        let ys = [0, inset.top, inset.bottom, height];
+        let graphics = crate::Playdate::get_graphics();
+        let mut pieces = Vec::with_capacity(9);
+        for row in 0..3 {
+            for column in 0..3 {
+                let (w, h) = (xs[column + 1] - xs[column], ys[row + 1] - ys[row]);
+                if w <= 0 || h <= 0 {
+                    pieces.push(None);
+                    continue;
+                }
+                let mut piece = Bitmap::new(w as u32, h as u32,
+                                            Color::SolidColor(SolidColor::kColorClear))?;
+                {
+                    let _target = graphics.push_context(&mut piece);
+                    bitmap.draw(-xs[column], -ys[row], BitmapFlip::kBitmapUnflipped);
+                }
+                pieces.push(Some(piece));
+            }
+        }
+        Ok(Self {
+            pieces,
+            size: (width, height),
+            left: inset.left,
+            right: width - inset.right,
+            top: inset.top,
+            bottom: height - inset.bottom,
+            edges: Fill::Tile,
+            center: Fill::Tile,
+        })
+    }
+
+    pub fn load(path: &str, inset: Rect) -> Result<Self> {
+        let bitmap = Bitmap::load(path)?;
+        Self::new(&bitmap, inset)
+    }
+
+    pub fn with_fill(mut self, edges: Fill, center: Fill) -> Self {
+        self.edges = edges;
+        self.center = center;
+        self
+    }
+
+    // Size of the source bitmap.
+    pub fn size(&self) -> (i32, i32) {
+        self.size
+    }
+
+    // The corners alone: the smallest box drawn without clipping.
+    pub fn min_size(&self) -> (i32, i32) {
+        (self.left + self.right, self.top + self.bottom)
+    }
+
+    // Border widths as (left, right, top, bottom).
+    pub fn insets(&self) -> (i32, i32, i32, i32) {
+        (self.left, self.right, self.top, self.bottom)
+    }
+
+    // Cell edges for a box; below `min_size` the corners keep their size and
+    // run past the box, which `draw` clips.
+    fn grid(&self, x: i32, y: i32, width: i32, height: i32) -> ([i32; 4], [i32; 4]) {
+        let (min_width, min_height) = self.min_size();
+        let (width, height) = (width.max(min_width), height.max(min_height));
+        ([x, x + self.left, x + width - self.right, x + width],
+         [y, y + self.top, y + height - self.bottom, y + height])
+    }
+
+    pub fn draw(&self, x: i32, y: i32, width: i32, height: i32) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let graphics = crate::Playdate::get_graphics();
+        let (min_width, min_height) = self.min_size();
+        let _clip = if width < min_width || height < min_height {
+            Some(graphics.push_clip_rect(x, y, width, height))
+        } else {
+            None
+        };
+        let (xs, ys) = self.grid(x, y, width, height);
+        for row in 0..3 {
+            for column in 0..3 {
+                let (w, h) = (xs[column + 1] - xs[column], ys[row + 1] - ys[row]);
+                let piece = match &self.pieces[row * 3 + column] {
+                    Some(piece) if w > 0 && h > 0 => piece,
+                    _ => continue,
+                };
+                let fill = match (row, column) {
+                    (1, 1) => self.center,
+                    (1, _) | (_, 1) => self.edges,
+                    _ => Fill::Tile,
+                };
+                match fill {
+                    Fill::Tile => piece.tile(xs[column], ys[row], w, h,
+                                             BitmapFlip::kBitmapUnflipped),
+                    Fill::Stretch => {
+                        let data = piece.data();
+                        let xscale = w as f32 / data.width as f32;
+                        let yscale = h as f32 / data.height as f32;
+                        // Scaling rounds up, so keep each piece in its cell.
+                        let _clip = graphics.push_clip_rect(xs[column], ys[row], w, h);
+                        piece.draw_scaled(xs[column], ys[row], xscale, yscale);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Draws a panel: the nine-slice when there is one, otherwise a white box
+// with a black border.
+fn draw_panel(graphics: &Graphics, frame: Option<&NineSlice>, x: i32, y: i32, w: i32, h: i32) {
+    match frame {
+        Some(frame) => frame.draw(x, y, w, h),
+        None => {
+            graphics.fill_rect(x, y, w, h, WHITE);
+            graphics.draw_rect(x, y, w, h, BLACK);
+            graphics.draw_rect(x + 1, y + 1, w - 2, h - 2, BLACK);
+        }
+    }
+}
+
+// Darkens everything already drawn with a 50% checkerboard.
+pub fn dim_screen(graphics: &Graphics) {
+    let dim = Pattern::with_mask([0; 8], Pattern::bayer2(32).bitmap());
+    graphics.fill_rect(0, 0, COLUMNS as i32, ROWS as i32, Color::Pattern(&dim));
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DialogResult {
+    Chosen(usize),
+    Cancelled,
+}
+
+// A modal message box with a row of buttons, centered on the screen over
+// a dimmed background. Left and right (or the crank) pick a button, A
+// chooses it and B cancels.
+pub struct Dialog {
+    font: Font,
+    title: String,
+    message: String,
+    buttons: Vec<String>,
+    selected: usize,
+    width: i32,
+    padding: i32,
+    frame: Option<NineSlice>,
+    crank: CrankTicks,
+}
+
+impl Dialog {
+    pub fn new(font: Font, message: &str) -> Self {
+        Self {
+            font,
+            title: String::new(),
+            message: String::from(message),
+            buttons: alloc::vec![String::from("OK")],
+            selected: 0,
+            width: 280,
+            padding: 8,
+            frame: None,
+            crank: CrankTicks::new(6),
+        }
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = String::from(title);
+        self
+    }
+
+    pub fn with_buttons(mut self, buttons: &[&str]) -> Self {
+        self.buttons = buttons.iter().map(|&button| String::from(button)).collect();
+        self.selected = 0;
+        self
+    }
+
+    pub fn with_width(mut self, width: i32) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn with_frame(mut self, frame: NineSlice) -> Self {
+        self.padding = frame.left.max(frame.right).max(frame.top).max(frame.bottom);
+        self.frame = Some(frame);
+        self
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn handle_input(&mut self, input: &Input) -> Option<DialogResult> {
+        let count = self.buttons.len();
+        if input.is_pushed(Buttons::kButtonLeft) {
+            self.selected = step(self.selected, -1, count, true);
+        }
+        if input.is_pushed(Buttons::kButtonRight) {
+            self.selected = step(self.selected, 1, count, true);
+        }
+        let ticks = self.crank.update(input.crank_change);
+        if ticks != 0 {
+            self.selected = step(self.selected, ticks.signum(), count, true);
+        }
+        if input.is_pushed(Buttons::kButtonA) && count > 0 {
+            Some(DialogResult::Chosen(self.selected))
+        } else if input.is_pushed(Buttons::kButtonB) {
+            Some(DialogResult::Cancelled)
+        } else {
+            None
+        }
+    }
+
+    pub fn draw(&self, graphics: &Graphics) {
+        dim_screen(graphics);
+        let font = &self.font;
+        let line_height = font.height();
+        let inner = self.width - 2 * self.padding;
+        let max_height = ROWS as i32 - 2 * self.padding - 3 * line_height;
+        let lines = TextLayout::new(font, 0, 0, inner, max_height).layout(&self.message).len();
+        let title_height = if self.title.is_empty() { 0 } else { line_height + 4 };
+        let message_height = lines as i32 * line_height;
+        let button_height = line_height + 4;
+        let height = 2 * self.padding + title_height + message_height + 6 + button_height;
+        let x = (COLUMNS as i32 - self.width) / 2;
+        let y = (ROWS as i32 - height) / 2;
+        draw_panel(graphics, self.frame.as_ref(), x, y, self.width, height);
+
+        let mut top = y + self.padding;
+        if !self.title.is_empty() {
+            TextLayout::new(font, x + self.padding, top, inner, line_height)
+                .alignment(Alignment::Center)
+                .wrap(false)
+                .draw(graphics, &self.title);
+            top += title_height;
+        }
+        TextLayout::new(font, x + self.padding, top, inner, message_height)
+            .draw(graphics, &self.message);
+        top += message_height + 6;
+
+        // Buttons share the row equally, first to last from the left.
+        let count = self.buttons.len().max(1) as i32;
+        let button_width = (inner - 4 * (count - 1)) / count;
+        for (i, button) in self.buttons.iter().enumerate() {
+            let cell = Cell {
+                row: 0,
+                column: i,
+                selected: i == self.selected,
+                x: x + self.padding + i as i32 * (button_width + 4),
+                y: top,
+                width: button_width,
+                height: button_height,
+            };
+            graphics.draw_rect(cell.x, cell.y, cell.width, cell.height, BLACK);
+            draw_cell_text(graphics, font, &cell, button);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Key {
+    // Pads a page's last row; it has no label and does nothing.
+    None,
+    Char(char),
+    Page,
+    Space,
+    Delete,
+    Done,
+}
+
+const PAGES: [&str; 3] = [
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+    "abcdefghijklmnopqrstuvwxyz",
+    "0123456789.,:;!?'\"-+*/=()@#&_",
+];
+const PAGE_LABELS: [&str; 3] = ["abc", "123", "ABC"];
+const KEY_COLUMNS: usize = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyboardEvent {
+    None,
+    Changed,
+    Done,
+    Cancelled,
+}
+
+// An on-screen keyboard across the bottom of the screen: a text field
+// above a grid of keys. The d-pad and crank move between keys, A types
+// the selected key and B deletes, or cancels once the text is empty.
+pub struct Keyboard {
+    font: Font,
+    text: String,
+    max_length: usize,
+    page: usize,
+    keys: Vec<Key>,
+    grid: GridView,
+}
+
+impl Keyboard {
+    pub fn new(font: Font, text: &str, max_length: usize) -> Self {
+        let cell_height = font.height() + 6;
+        let mut keyboard = Self {
+            font,
+            text: String::from(text),
+            max_length,
+            page: 0,
+            keys: Vec::new(),
+            grid: GridView::new(0, KEY_COLUMNS, COLUMNS as i32 / KEY_COLUMNS as i32, cell_height),
+        };
+        keyboard.grid.set_wrap_selection(true);
+        keyboard.set_page(0);
+        keyboard
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.chars().take(self.max_length).collect();
+    }
+
+    fn set_page(&mut self, page: usize) {
+        self.page = page % PAGES.len();
+        self.keys = PAGES[self.page].chars().map(Key::Char).collect();
+        while self.keys.len() % KEY_COLUMNS != KEY_COLUMNS - 4 {
+            self.keys.push(Key::None);
+        }
+        self.keys.extend_from_slice(&[Key::Page, Key::Space, Key::Delete, Key::Done]);
+        let rows = self.keys.len() / KEY_COLUMNS;
+        let (row, column) = self.grid.selection();
+        self.grid.set_size(rows, KEY_COLUMNS);
+        self.grid.set_selection(row, column);
+    }
+
+    // Height of the keyboard panel, drawn flush with the bottom.
+    pub fn height(&self) -> i32 {
+        let (_, grid_height) = self.grid.content_size(COLUMNS as i32);
+        grid_height + self.font.height() + 8
+    }
+
+    fn key(&self) -> Option<Key> {
+        let (row, column) = self.grid.selection();
+        self.keys.get(row * KEY_COLUMNS + column).copied()
+    }
+
+    pub fn handle_input(&mut self, input: &Input) -> KeyboardEvent {
+        self.grid.handle_input(input);
+        if input.is_pushed(Buttons::kButtonB) {
+            if self.text.pop().is_none() {
+                return KeyboardEvent::Cancelled;
+            }
+            return KeyboardEvent::Changed;
+        }
+        if !input.is_pushed(Buttons::kButtonA) {
+            return KeyboardEvent::None;
+        }
+        let typed = match self.key() {
+            Some(Key::Char(c)) => c,
+            Some(Key::Space) => ' ',
+            Some(Key::Page) => {
+                self.set_page(self.page + 1);
+                return KeyboardEvent::None;
+            }
+            Some(Key::Delete) => {
+                return if self.text.pop().is_some() {
+                    KeyboardEvent::Changed
+                } else {
+                    KeyboardEvent::None
+                };
+            }
+            Some(Key::Done) => return KeyboardEvent::Done,
+            Some(Key::None) | None => return KeyboardEvent::None,
+        };
+        if self.text.chars().count() >= self.max_length {
+            return KeyboardEvent::None;
+        }
+        self.text.push(typed);
+        KeyboardEvent::Changed
+    }
+
+    pub fn draw(&mut self, graphics: &Graphics) {
+        let width = COLUMNS as i32;
+        let line_height = self.font.height();
+        let y = ROWS as i32 - self.height();
+        graphics.fill_rect(0, y, width, self.height(), WHITE);
+        graphics.draw_line(0, y, width, y, 1, BLACK);
+
+        // The field shows the end of the text, followed by a cursor.
+        graphics.set_font(self.font);
+        let mut shown = self.text.as_str();
+        while self.font.text_width(shown, 0) > width - 12 {
+            let mut chars = shown.chars();
+            chars.next();
+            shown = chars.as_str();
+        }
+        graphics.draw_text(shown, StringEncoding::kUTF8Encoding, 4, y + 4);
+        let cursor = 4 + self.font.text_width(shown, 0) + 1;
+        graphics.fill_rect(cursor, y + 4, 2, line_height, BLACK);
+
+        let top = y + line_height + 8;
+        let (keys, page, font) = (&self.keys, self.page, &self.font);
+        self.grid.draw(graphics, 0, top, width, ROWS as i32 - top, |graphics, cell| {
+            let mut buffer = [0u8; 4];
+            let label = match keys[cell.row * KEY_COLUMNS + cell.column] {
+                Key::Char(c) => &*c.encode_utf8(&mut buffer),
+                Key::Page => PAGE_LABELS[page],
+                Key::Space => "SPC",
+                Key::Delete => "DEL",
+                Key::Done => "OK",
+                Key::None => return,
+            };
+            draw_cell_text(graphics, font, cell, label);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 12x12 source with 3px borders left and top, 4px right and bottom.
+    fn frame() -> NineSlice {
+        NineSlice {
+            pieces: Vec::new(),
+            size: (12, 12),
+            left: 3,
+            right: 4,
+            top: 3,
+            bottom: 4,
+            edges: Fill::Stretch,
+            center: Fill::Stretch,
+        }
+    }
+
+    #[test]
+    fn grid_stretches_the_middle() {
+        let (xs, ys) = frame().grid(10, 20, 30, 16);
+        assert_eq!(xs, [10, 13, 36, 40]);
+        assert_eq!(ys, [20, 23, 32, 36]);
+    }
+
+    #[test]
+    fn grid_keeps_corners_whole_below_min_size() {
+        let frame = frame();
+        assert_eq!(frame.min_size(), (7, 7));
+        // Narrower than the corners: the middle column closes up and the
+        // right corner runs past the box, to be clipped by `draw`.
+        let (xs, ys) = frame.grid(10, 20, 4, 16);
+        assert_eq!(xs, [10, 13, 13, 17]);
+        assert_eq!(ys, [20, 23, 32, 36]);
+        let (xs, ys) = frame.grid(0, 0, 1, 1);
+        assert_eq!(xs, [0, 3, 3, 7]);
+        assert_eq!(ys, [0, 3, 3, 7]);
+    }
+
+    fn input(pushed: Buttons, released: Buttons, crank_change: f32) -> Input {
+        Input { current: pushed, pushed, released, crank_change, crank_docked: false }
+    }
+
+    fn pushed(buttons: Buttons) -> Input {
+        input(buttons, Buttons(0), 0.0)
+    }
+
+    fn crank(change: f32) -> Input {
+        input(Buttons(0), Buttons(0), change)
+    }
+
+    #[test]
+    fn crank_ticks_carry_the_remainder() {
+        let mut ticks = CrankTicks::new(6);
+        assert_eq!(ticks.update(30.0), 0);
+        assert_eq!(ticks.update(30.0), 1);
+        // -90 is one tick back with 30 degrees still owed.
+        assert_eq!(ticks.update(-90.0), -1);
+        assert_eq!(ticks.update(150.0), 2);
+        assert_eq!(ticks.update(59.0), 0);
+        ticks.reset();
+        assert_eq!(ticks.update(1.0), 0);
+        assert_eq!(ticks.update(700.0), 11);
+        assert_eq!(CrankTicks::new(0).update(359.0), 0);
+    }
+
+    #[test]
+    fn grid_selection_clamps_and_wraps() {
+        let mut grid = GridView::new(3, 4, 20, 10);
+        grid.set_selection(5, 9);
+        assert_eq!(grid.selection(), (2, 3));
+        grid.select_next_row();
+        grid.select_next_column();
+        assert_eq!(grid.selection(), (2, 3));
+        grid.set_wrap_selection(true);
+        grid.select_next_row();
+        assert_eq!(grid.selection(), (0, 3));
+        grid.select_next_column();
+        assert_eq!(grid.selection(), (0, 0));
+        grid.select_previous_column();
+        grid.select_previous_row();
+        assert_eq!(grid.selection(), (2, 3));
+        grid.set_size(2, 2);
+        assert_eq!(grid.selection(), (1, 1));
+
+        let mut empty = GridView::new(0, 0, 20, 10);
+        empty.select_next_row();
+        empty.set_selection(3, 3);
+        assert_eq!((empty.columns(), empty.selection()), (1, (0, 0)));
+    }
+
+    #[test]
+    fn grid_moves_with_the_d_pad_and_crank() {
+        let mut grid = GridView::list(10, 20);
+        assert!(grid.handle_input(&pushed(Buttons::kButtonDown)));
+        assert_eq!(grid.selection(), (1, 0));
+        assert!(!grid.handle_input(&pushed(Buttons::kButtonUp | Buttons::kButtonDown)));
+        assert!(!grid.handle_input(&pushed(Buttons::kButtonRight)));
+        // Six ticks a turn: 120 degrees is two rows.
+        assert!(grid.handle_input(&crank(120.0)));
+        assert_eq!(grid.selection(), (3, 0));
+        assert!(!grid.handle_input(&crank(-30.0)));
+        assert!(grid.handle_input(&crank(-30.0)));
+        assert_eq!(grid.selection(), (2, 0));
+    }
+
+    #[test]
+    fn grid_scrolls_to_keep_the_selection_in_view() {
+        let mut grid = GridView::list(10, 20);
+        grid.set_scroll_lerp(1.0);
+        assert_eq!(grid.cell_size(100), (100, 20));
+        assert_eq!(grid.content_size(100), (100, 200));
+        grid.update_scroll(100, 50);
+        assert_eq!(grid.scroll_position(), (0, 0));
+        assert!(!grid.needs_display());
+        // Below the view: scrolled just far enough to show it.
+        grid.set_selection(5, 0);
+        grid.update_scroll(100, 50);
+        assert_eq!(grid.scroll_position(), (0, 70));
+        // Above it: scrolled to its top.
+        grid.set_selection(1, 0);
+        grid.update_scroll(100, 50);
+        assert_eq!(grid.scroll_position(), (0, 20));
+        grid.set_selection(9, 0);
+        grid.update_scroll(100, 50);
+        assert_eq!(grid.scroll_position(), (0, 150));
+        // Manual scrolling stays inside the content.
+        grid.scroll_to(0, 1000);
+        grid.update_scroll(100, 50);
+        assert_eq!(grid.scroll_position(), (0, 150));
+        grid.scroll_to(0, -5);
+        grid.update_scroll(100, 50);
+        assert_eq!(grid.scroll_position(), (0, 0));
+    }
+
+    #[test]
+    fn grid_scrolls_columns_with_spacing() {
+        let mut grid = GridView::new(2, 10, 30, 30);
+        grid.set_spacing(5, 0);
+        grid.set_scroll_lerp(1.0);
+        assert_eq!(grid.content_size(100), (345, 60));
+        grid.set_selection(0, 9);
+        grid.update_scroll(100, 60);
+        assert_eq!(grid.scroll_position(), (245, 0));
+    }
+
+    #[test]
+    fn grid_scroll_eases_then_settles() {
+        let mut grid = GridView::list(10, 20);
+        grid.set_scroll_lerp(0.5);
+        grid.set_selection(5, 0);
+        grid.update_scroll(100, 50);
+        assert_eq!(grid.scroll_position(), (0, 35));
+        assert!(grid.needs_display());
+        let mut frames = 1;
+        while grid.needs_display() {
+            grid.update_scroll(100, 50);
+            frames += 1;
+            assert!(frames < 20);
+        }
+        assert_eq!(grid.scroll_position(), (0, 70));
+    }
+
+    #[test]
+    fn button_activates_on_release_while_focused() {
+        let a = Buttons::kButtonA;
+        let mut button = Button::new("OK");
+        assert!(!button.handle_input(&input(a, a, 0.0)));
+        button.set_focused(true);
+        assert!(!button.handle_input(&pushed(a)));
+        assert!(button.is_pressed());
+        assert!(!button.handle_input(&crank(0.0)));
+        assert!(button.handle_input(&input(Buttons(0), a, 0.0)));
+        assert!(!button.is_pressed());
+        // A tap within one frame.
+        assert!(button.handle_input(&input(a, a, 0.0)));
+        // Losing focus mid-press cancels it.
+        button.handle_input(&pushed(a));
+        button.set_focused(false);
+        button.set_focused(true);
+        assert!(!button.handle_input(&input(Buttons(0), a, 0.0)));
+    }
+
+    #[test]
+    fn slider_values_snap_to_steps() {
+        let mut slider = Slider::new(0, 10, 5).with_step(2);
+        assert_eq!(slider.value(), 6);
+        slider.set_value(3);
+        assert_eq!(slider.value(), 4);
+        slider.set_value(11);
+        assert_eq!(slider.value(), 10);
+        slider.set_value(-3);
+        assert_eq!(slider.value(), 0);
+        // The last step falls short of `max`.
+        let mut slider = Slider::new(0, 9, 9).with_step(4);
+        assert_eq!(slider.value(), 8);
+        slider.set_value(100);
+        assert_eq!(slider.value(), 8);
+    }
+
+    #[test]
+    fn slider_moves_with_the_d_pad_and_crank_while_focused() {
+        let mut slider = Slider::new(0, 10, 5);
+        assert!(!slider.handle_input(&pushed(Buttons::kButtonRight)));
+        slider.set_focused(true);
+        assert!(slider.handle_input(&pushed(Buttons::kButtonRight)));
+        assert_eq!(slider.value(), 6);
+        assert!(!slider.handle_input(&pushed(Buttons::kButtonLeft | Buttons::kButtonRight)));
+        // Twelve ticks a turn: 65 degrees is two steps.
+        assert!(slider.handle_input(&crank(65.0)));
+        assert_eq!(slider.value(), 8);
+        // 5 degrees were left over, so -180 is five steps back, not six.
+        assert!(slider.handle_input(&crank(-180.0)));
+        assert_eq!(slider.value(), 3);
+        slider.set_value(10);
+        assert!(!slider.handle_input(&pushed(Buttons::kButtonRight)));
+    }
+
+    #[test]
+    fn slider_knob_tracks_the_fraction() {
+        let mut slider = Slider::new(0, 10, 5);
+        assert_eq!(slider.fraction(), 0.5);
+        assert_eq!(slider.knob_x(120, 20), 50);
+        slider.set_value(10);
+        assert_eq!(slider.knob_x(120, 20), 100);
+        assert_eq!(slider.knob_x(10, 20), 0);
+        assert_eq!(Slider::new(3, 3, 7).fraction(), 0.0);
+    }
+}
+